@@ -18,6 +18,11 @@
 
 #[macro_use]
 extern crate serde_derive;
+#[macro_use]
+extern crate log;
+extern crate rand;
+extern crate rusqlite;
+extern crate tungstenite;
 
 mod stpsyr;
 pub use stpsyr::*;
@@ -16,14 +16,232 @@
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
+extern crate log;
+extern crate serde_json;
 extern crate stpsyr;
+
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::process;
+
 use stpsyr::*;
-extern crate serde_json;
+
+const USAGE: &str = "usage:
+    stpsyr new <map.csv> <game.json>
+    stpsyr orders <game.json> <power> [orders file, or stdin]
+    stpsyr adjudicate <game.json>
+    stpsyr render <game.json> <output.svg|output.png> [--legend]
+    stpsyr show <game.json>
+    stpsyr repl <game.json>
+    stpsyr serve [address, default 127.0.0.1:8000] [games.db]
+    stpsyr pbem <game.json> <players file> <maildir> <outbox>
+    stpsyr validate-map <map.csv>";
+
 fn main() {
-    let mut s = Stpsyr::new("data/standard.csv");
-    s.parse(&Power::from("Italy"), "A ven-tyr".to_string());
-    s.apply();
-    let json_value = serde_json::to_string(&s).unwrap();
-    s.render_to_file("meems.svg".to_string());
-    println!("{}", json_value);
+    // diagnostics go to stderr at the level in STPSYR_LOG (e.g.
+    //   STPSYR_LOG=debug), and nowhere by default
+    if let Some(level) = env::var("STPSYR_LOG")
+        .ok()
+        .and_then(|level| level.parse::<log::LevelFilter>().ok())
+    {
+        if log::set_logger(&StderrLogger).is_ok() {
+            log::set_max_level(level);
+        }
+    }
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|a| &a[..]).collect();
+
+    let result = match &args[..] {
+        ["new", mapfile, gamefile] => {
+            let problems = Stpsyr::validate_map(mapfile);
+            if problems.is_empty() {
+                save(gamefile, &Stpsyr::new(mapfile))
+            } else {
+                Err(problems.join("\n"))
+            }
+        }
+        ["orders", gamefile, power, rest @ ..] if rest.len() <= 1 => {
+            load(gamefile).and_then(|mut game| {
+                let orders = match rest.first() {
+                    Some(&path) if path != "-" => fs::read_to_string(path)
+                        .map_err(|err| format!("can't read {}: {}", path, err))?,
+                    _ => {
+                        let mut orders = String::new();
+                        io::stdin()
+                            .read_to_string(&mut orders)
+                            .map_err(|err| format!("can't read stdin: {}", err))?;
+                        orders
+                    }
+                };
+                let power = game
+                    .powers()
+                    .into_iter()
+                    .find(|p| p.name.eq_ignore_ascii_case(power))
+                    .ok_or_else(|| format!("{} isn't a power in this game", power))?;
+                let submitted = orders.lines().filter(|l| !l.trim().is_empty()).count();
+                let accepted = game.parse(&power, orders);
+                println!("{}/{} accepted", accepted, submitted);
+                if accepted == 0 {
+                    return Err(format!("none of {}'s orders were accepted", power.name));
+                }
+                save(gamefile, &game)
+            })
+        }
+        ["adjudicate", gamefile] => load(gamefile).and_then(|mut game| {
+            game.apply();
+            println!("{} {}", game.phase, game.year);
+            save(gamefile, &game)
+        }),
+        ["render", gamefile, output, flags @ ..] if flags.iter().all(|&f| f == "--legend") => {
+            load(gamefile).and_then(|game| {
+                let options = rendering::RenderOptions {
+                    show_legend: !flags.is_empty(),
+                    ..Default::default()
+                };
+                if output.ends_with(".png") {
+                    game.render_png_to_file_with(output.to_string(), &options)
+                } else {
+                    game.render_to_file_with(output.to_string(), &options)
+                }
+                .map_err(|err| err.to_string())
+            })
+        }
+        ["show", gamefile] => load(gamefile).map(|game| print!("{}", game.summary())),
+        ["repl", gamefile] => load(gamefile).and_then(|game| {
+            let mut repl = repl::Repl::new(game);
+            let stdin = io::stdin();
+            repl.run(stdin.lock(), io::stdout());
+            save(gamefile, &repl.game)
+        }),
+        ["serve", rest @ ..] if rest.len() <= 2 => {
+            let address = rest.first().cloned().unwrap_or("127.0.0.1:8000");
+            let mut server = server::Server::new("data");
+            let storage = rest.get(1).map(|path| {
+                storage::Storage::open(path)
+                    .map_err(|err| format!("can't open {}: {}", path, err))
+                    .and_then(|storage| server.attach_storage(storage))
+            });
+            match (storage, next_port(address)) {
+                (Some(Err(err)), _) | (_, Err(err)) => Err(err),
+                (_, Ok(events_address)) => {
+                    println!(
+                        "listening on http://{} (events on ws://{})",
+                        address, events_address
+                    );
+                    server.serve(address, &events_address)
+                }
+            }
+        }
+        ["pbem", gamefile, playersfile, maildir, outbox] => load(gamefile).and_then(|game| {
+            let mut judge = pbem::Judge::new(game, maildir, outbox);
+            let players = fs::read_to_string(playersfile)
+                .map_err(|err| format!("can't read {}: {}", playersfile, err))?;
+            // one "<address> <power> [password]" per line
+            for line in players.lines().map(str::trim) {
+                let words: Vec<&str> = line.split_whitespace().collect();
+                match words[..] {
+                    [] => {}
+                    [comment, ..] if comment.starts_with('#') => {}
+                    [address, power] => judge.add_player(address, Power::from(power), None),
+                    [address, power, password] => {
+                        judge.add_player(address, Power::from(power), Some(password.to_string()))
+                    }
+                    _ => return Err(format!("{}: can't read the line {}", playersfile, line)),
+                }
+            }
+            let handled = judge
+                .check_mail()
+                .map_err(|err| format!("can't read mail from {}: {}", maildir, err))?;
+            let adjudicated = judge
+                .tick(&deadlines::SystemClock)
+                .map_err(|err| format!("can't write to {}: {}", outbox, err))?;
+            println!(
+                "{} message(s){}",
+                handled,
+                if adjudicated { ", adjudicated" } else { "" }
+            );
+            save(gamefile, &judge.game)
+        }),
+        ["validate-map", mapfile] => {
+            let problems = Stpsyr::validate_map(mapfile);
+            if problems.is_empty() {
+                println!("{}: ok", mapfile);
+                Ok(())
+            } else {
+                Err(problems.join("\n"))
+            }
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+// the same address with the port after, e.g. "127.0.0.1:8001" for
+//   "127.0.0.1:8000"
+fn next_port(address: &str) -> Result<String, String> {
+    address
+        .rsplit_once(':')
+        .and_then(|(host, port)| {
+            Some(format!(
+                "{}:{}",
+                host,
+                port.parse::<u16>().ok()?.checked_add(1)?
+            ))
+        })
+        .ok_or_else(|| format!("{} isn't an address like 127.0.0.1:8000", address))
+}
+
+// games are saved as JSON, so they can be inspected and edited by hand
+fn load(gamefile: &str) -> Result<Stpsyr, String> {
+    let json =
+        fs::read_to_string(gamefile).map_err(|err| format!("can't read {}: {}", gamefile, err))?;
+    serde_json::from_str(&json).map_err(|err| format!("{} isn't a saved game: {}", gamefile, err))
+}
+
+fn save(gamefile: &str, game: &Stpsyr) -> Result<(), String> {
+    let json = serde_json::to_string(game).unwrap();
+    fs::write(gamefile, json).map_err(|err| format!("can't write {}: {}", gamefile, err))
+}
+
+// writes each log record as its level, its message and its fields, e.g.
+//   "DEBUG ven - tyr succeeded phase=Spring Diplomacy year=1901"
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut fields = Fields(String::new());
+        let _ = record.key_values().visit(&mut fields);
+        eprintln!("{:<5} {}{}", record.level(), record.args(), fields.0);
+    }
+
+    fn flush(&self) {}
+}
+
+struct Fields(String);
+
+impl<'kvs> log::kv::VisitSource<'kvs> for Fields {
+    fn visit_pair(
+        &mut self,
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        self.0.push_str(&format!(" {}={}", key, value));
+        Ok(())
+    }
 }
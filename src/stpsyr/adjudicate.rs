@@ -4,14 +4,32 @@ impl Stpsyr {
     // this is the function that actually moves units when their resolution is
     //   successful
     pub fn apply_resolved(&mut self) {
-        // anything that got moved on top of (but maybe it also moved away)
-        let mut dislodged: Vec<(Province, Unit)> = vec![];
+        // anything that got moved on top of (but maybe it also moved away),
+        //   along with where the attacker came from if it wasn't convoyed
+        let mut dislodged: Vec<(Province, Unit, Option<Province>)> = vec![];
         // anything that left an empty space (but maybe something also moved in)
         let mut moved_away: Vec<&Province> = vec![];
 
+        // a failed move leaves its destination contested, so no one can
+        //   retreat there, unless it never had a path there to begin with
+        let failed_moves: Vec<Order> = self
+            .orders
+            .iter()
+            .filter(|o| matches!(o.action, Action::Move { .. }) && !o.resolution)
+            .cloned()
+            .collect();
+        let mut bounced: Vec<(Province, Province)> = vec![];
+        for order in failed_moves {
+            if let Action::Move { ref to, convoyed } = order.action {
+                if !convoyed || !self.convoy_paths(&order).is_empty() {
+                    bounced.push((order.province.clone(), to.clone()));
+                }
+            }
+        }
+
         let old_map = self.map.clone();
         for order in &self.orders {
-            if let Action::Move { ref to, .. } = order.action {
+            if let Action::Move { ref to, convoyed } = order.action {
                 if order.resolution {
                     // we have a successful move
                     let from_idx = self
@@ -22,7 +40,12 @@ impl Stpsyr {
                     let to_idx = self.map.iter().position(|r| r.province == *to).unwrap();
 
                     if let Some(ref unit) = self.map[to_idx].unit {
-                        dislodged.push((to.clone(), unit.clone()));
+                        let attacker = if convoyed {
+                            None
+                        } else {
+                            Some(order.province.clone())
+                        };
+                        dislodged.push((to.clone(), unit.clone(), attacker));
                     }
 
                     self.map[to_idx].unit = old_map[from_idx].unit.clone();
@@ -33,20 +56,33 @@ impl Stpsyr {
                     }
 
                     moved_away.push(&order.province);
-                } else {
-                    self.contested.insert(order.province.clone());
                 }
             }
         }
 
+        // a unit dislodged by the unit it was attacking doesn't leave the
+        //   attacker's province contested
+        for (from, to) in bounced {
+            if !dislodged
+                .iter()
+                .any(|(p, _, a)| *p == from && a.as_ref().is_some_and(|a| a.name == to.name))
+            {
+                self.contested.insert(to);
+            }
+        }
+
         // now we can do processing for dislodged and moved_away
         for region in &mut self.map {
             let p_dislodged = dislodged.iter().find(|d| d.0 == region.province);
             let p_moved_away = moved_away.contains(&&region.province);
-            if let Some(dislodgement) = p_dislodged {
+            if let Some((province, unit, attacker)) = p_dislodged {
                 if !p_moved_away {
                     // dislodged and not moved away: add it to the list
-                    self.dislodged.push(dislodgement.clone());
+                    self.dislodged.push((province.clone(), unit.clone()));
+                    if let Some(attacker) = attacker {
+                        self.attacked_from
+                            .push((province.clone(), attacker.clone()));
+                    }
                 }
             } else if p_moved_away {
                 // moved away and not dislodged: clear from map
@@ -377,7 +413,7 @@ impl Stpsyr {
                     *from == *province && *to == *dest,
                 _ => false
             } &&
-            attacked_power.as_ref().map_or(true, |power| *power != o.owner))
+            attacked_power.as_ref().is_none_or(|power| *power != o.owner))
             .map(|o| o.id).collect();
 
         1 + supports.iter().filter(|&id| self.resolve(*id)).count()
@@ -1,22 +1,60 @@
+use stpsyr::observer::EngineEvent;
 use stpsyr::types::*;
 
 impl Stpsyr {
-    // the publicly exposed function to modify self.adjusts
-    pub fn add_adjust(&mut self, owner: Power, province: Province, action: AdjustAction) {
+    // the publicly exposed function to modify self.adjusts; returns whether
+    //   the adjustment was accepted, replacing any earlier one for the same
+    //   province
+    pub fn add_adjust(&mut self, owner: Power, province: Province, action: AdjustAction) -> bool {
         match self.phase {
             Phase::Builds => {}
             _ => panic!("add_adjust called during non-build phase"),
         }
 
+        // the earlier adjustment doesn't count toward the number allowed
+        //   while checking its replacement
+        let earlier = self
+            .adjusts
+            .iter()
+            .position(|a| a.owner == owner && a.province == province);
+        let removed = earlier.map(|i| self.adjusts.remove(i));
+
+        if !self.adjust_is_legal(&owner, &province, &action) {
+            if let (Some(i), Some(adjust)) = (earlier, removed) {
+                self.adjusts.insert(i, adjust);
+            }
+            return false;
+        }
+
+        // everything's good
+        let adjust = Adjust {
+            owner,
+            province,
+            action,
+        };
+        match earlier {
+            Some(i) => self.adjusts.insert(i, adjust),
+            None => self.adjusts.push(adjust),
+        }
+        true
+    }
+
+    // the checks behind add_adjust, also used to generate legal orders
+    pub fn adjust_is_legal(
+        &self,
+        owner: &Power,
+        province: &Province,
+        action: &AdjustAction,
+    ) -> bool {
         // find difference in SC and unit counts
-        let delta = if let Some(count) = self.sc_counts().get(&owner) {
+        let delta = if let Some(count) = self.sc_counts().get(owner) {
             *count as i32
         } else {
-            return;
-        } - if let Some(count) = self.unit_counts().get(&owner) {
+            return false;
+        } - if let Some(count) = self.unit_counts().get(owner) {
             *count as i32
         } else {
-            return;
+            return false;
         };
 
         // find existing number of adjust orders for this power
@@ -30,11 +68,11 @@ impl Stpsyr {
                      province: p,
                      ..
                  }| {
-                    if owner == *o && province == *p {
+                    if *owner == *o && *province == *p {
                         dup = true;
                         true
                     } else {
-                        owner == *o
+                        *owner == *o
                     }
                 },
             )
@@ -42,56 +80,87 @@ impl Stpsyr {
 
         // fail if we're not allowed to build or destroy at all
         if dup
-            || match action {
+            || match *action {
                 AdjustAction::Disband => delta >= 0 || -num == delta,
                 AdjustAction::Build { .. } => delta <= 0 || num == delta,
             }
         {
-            return;
+            return false;
         }
 
         // now we have to check if the given province is a valid one to build/
         // destroy in
-        let region = self.map.iter().find(|r| r.province == province).unwrap();
-        if !match action {
-            AdjustAction::Disband => region.unit.as_ref().is_some_and(|u| u.owner == owner),
-            AdjustAction::Build { unit_type: t } => {
+        let region = if let Some(region) = self.get_region(province) {
+            region
+        } else {
+            return false;
+        };
+        match *action {
+            AdjustAction::Disband => region.unit.as_ref().is_some_and(|u| u.owner == *owner),
+            AdjustAction::Build {
+                unit_type: t,
+                coast,
+            } => {
                 region.unit.is_none()
-                    && region.home_power.as_ref().is_some_and(|p| *p == owner)
+                    && region.home_power.as_ref().is_some_and(|p| *p == *owner)
+                    && region.owner.as_ref().is_some_and(|p| *p == *owner)
                     && match t {
-                        UnitType::Army => !region.army_borders.is_empty(),
-                        UnitType::Fleet => !region.fleet_borders.is_empty(),
+                        UnitType::Army => !region.army_borders.is_empty() && coast.is_none(),
+                        // a fleet needs a coast exactly when the province has
+                        //   more than one
+                        UnitType::Fleet => {
+                            let coasts = region.coasts();
+                            !region.fleet_borders.is_empty()
+                                && coast.map_or(coasts.is_empty(), |c| coasts.contains(&c))
+                        }
                     }
             }
-        } {
-            return;
         }
-
-        // everything's good
-        self.adjusts.push(Adjust {
-            owner,
-            province,
-            action,
-        });
     }
 
     // the publicly exposed function that is called once all adjusts have been
     //   added
     pub fn apply_adjusts(&mut self) {
+        self.record_nmrs();
+        for adjust in self.civil_disorder_disbands() {
+            info!(
+                phase:% = self.phase, year = self.year, power = &adjust.owner.name[..];
+                "civil disorder: {}",
+                adjust
+            );
+            self.adjusts.push(adjust);
+        }
+
         for adjust in &self.adjusts {
             let region = self
                 .map
                 .iter_mut()
                 .find(|r| r.province == adjust.province)
                 .unwrap();
-            match adjust.action {
-                AdjustAction::Disband => region.unit = None,
-                AdjustAction::Build { unit_type } => {
-                    region.unit = Some(Unit {
+            let event = match adjust.action {
+                AdjustAction::Disband => {
+                    region.unit.take().map(|unit| EngineEvent::UnitDisbanded {
+                        province: adjust.province.clone(),
+                        unit,
+                    })
+                }
+                AdjustAction::Build { unit_type, coast } => {
+                    let unit = Unit {
                         owner: adjust.owner.clone(),
                         unit_type,
+                    };
+                    region.unit = Some(unit.clone());
+                    if coast.is_some() {
+                        region.province.coast = coast;
+                    }
+                    Some(EngineEvent::UnitBuilt {
+                        province: region.province.clone(),
+                        unit,
                     })
                 }
+            };
+            if let Some(event) = event {
+                self.notify(event);
             }
         }
 
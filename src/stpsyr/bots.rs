@@ -0,0 +1,218 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use stpsyr::tournament::Player;
+use stpsyr::types::*;
+
+// a RandomBot picks uniformly among the legal orders for each of its units
+pub struct RandomBot {
+    rng: StdRng,
+}
+
+impl RandomBot {
+    // the seed makes games reproducible
+    pub fn new(seed: u64) -> RandomBot {
+        RandomBot {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Player for RandomBot {
+    fn orders(&mut self, game: &Stpsyr, power: &Power) -> Vec<(Province, LegalOrder)> {
+        // orders are submitted to a fork as we go so that builds and
+        //   disbands stay within the allowed count
+        let mut game = game.fork();
+        let mut orders = vec![];
+        for province in game.orderable_provinces(power) {
+            let mut legal = game.legal_orders(&province);
+            if legal.is_empty() {
+                continue;
+            }
+            let order = legal.swap_remove(self.rng.gen_range(0..legal.len()));
+            game.submit(power, province.clone(), order.clone());
+            orders.push((province, order));
+        }
+        orders
+    }
+}
+
+// a GreedyBot sends every unit toward the nearest supply center it doesn't
+//   own, preferring centers held by the weakest powers
+#[derive(Default)]
+pub struct GreedyBot;
+
+impl GreedyBot {
+    pub fn new() -> GreedyBot {
+        GreedyBot
+    }
+
+    fn moves(&mut self, game: &Stpsyr, power: &Power) -> Vec<(Province, LegalOrder)> {
+        let mut orders = vec![];
+        let mut claimed: Vec<Province> = vec![];
+        for province in game.orderable_provinces(power) {
+            let unit = game.get_unit(&province).unwrap();
+
+            // a unit sitting on a center it doesn't own yet stays until the
+            //   center changes hands in the fall
+            let region = game.get_region(&province).unwrap();
+            if region.sc && region.owner.as_ref() != Some(power) {
+                claimed.push(province.clone());
+                orders.push((province, LegalOrder::Order(Action::Hold)));
+                continue;
+            }
+
+            let best = game
+                .legal_orders(&province)
+                .into_iter()
+                .filter_map(|order| match order {
+                    LegalOrder::Order(Action::Move {
+                        ref to,
+                        convoyed: false,
+                    }) if !claimed.contains(to)
+                        && !game.get_unit(to).is_some_and(|u| u.owner == *power) =>
+                    {
+                        target_score(game, power, to, unit.unit_type).map(|s| (s, order.clone()))
+                    }
+                    _ => None,
+                })
+                .min_by_key(|&(score, _)| score);
+
+            let here = target_score(game, power, &province, unit.unit_type);
+            match best {
+                Some((score, LegalOrder::Order(action))) if here.is_none_or(|h| score < h) => {
+                    if let Action::Move { ref to, .. } = action {
+                        claimed.push(to.clone());
+                    }
+                    orders.push((province, LegalOrder::Order(action)));
+                }
+                _ => {
+                    claimed.push(province.clone());
+                    orders.push((province, LegalOrder::Order(Action::Hold)));
+                }
+            }
+        }
+        orders
+    }
+
+    fn retreats(&mut self, game: &Stpsyr, power: &Power) -> Vec<(Province, LegalOrder)> {
+        let mut orders = vec![];
+        let mut claimed: Vec<Province> = vec![];
+        for province in game.orderable_provinces(power) {
+            let unit_type = game
+                .dislodged
+                .iter()
+                .find(|(p, _)| *p == province)
+                .map(|(_, u)| u.unit_type)
+                .unwrap();
+            let best = game
+                .legal_orders(&province)
+                .into_iter()
+                .filter_map(|order| match order {
+                    LegalOrder::Retreat(RetreatAction::Move { ref to })
+                        if !claimed.contains(to) =>
+                    {
+                        target_score(game, power, to, unit_type).map(|s| (s, order.clone()))
+                    }
+                    _ => None,
+                })
+                .min_by_key(|&(score, _)| score);
+            match best {
+                Some((_, LegalOrder::Retreat(RetreatAction::Move { to }))) => {
+                    claimed.push(to.clone());
+                    orders.push((province, LegalOrder::Retreat(RetreatAction::Move { to })));
+                }
+                _ => orders.push((province, LegalOrder::Retreat(RetreatAction::Disband))),
+            }
+        }
+        orders
+    }
+
+    fn adjusts(&mut self, game: &Stpsyr, power: &Power) -> Vec<(Province, LegalOrder)> {
+        let mut game = game.fork();
+        let mut orders = vec![];
+
+        // disband the units furthest from anything worth taking first, and
+        //   build fleets only while there are fewer fleets than armies
+        let mut provinces = game.orderable_provinces(power);
+        provinces.sort_by_key(|p| {
+            game.get_unit(p).map_or(0, |u| {
+                usize::MAX - target_score(&game, power, p, u.unit_type).map_or(0, |s| s.0)
+            })
+        });
+        for province in provinces {
+            let (fleets, armies) = unit_type_counts(&game, power);
+            let legal = game.legal_orders(&province);
+            let order = legal
+                .iter()
+                .find(|order| match **order {
+                    LegalOrder::Adjust(AdjustAction::Build {
+                        unit_type: UnitType::Fleet,
+                        ..
+                    }) => fleets < armies,
+                    _ => false,
+                })
+                .or_else(|| legal.first())
+                .cloned();
+            if let Some(order) = order {
+                game.submit(power, province.clone(), order.clone());
+                orders.push((province, order));
+            }
+        }
+        orders
+    }
+}
+
+impl Player for GreedyBot {
+    fn orders(&mut self, game: &Stpsyr, power: &Power) -> Vec<(Province, LegalOrder)> {
+        match game.phase {
+            Phase::SpringDiplomacy | Phase::FallDiplomacy => self.moves(game, power),
+            Phase::SpringRetreats | Phase::FallRetreats => self.retreats(game, power),
+            Phase::Builds => self.adjusts(game, power),
+        }
+    }
+}
+
+// how attractive it is to be in a province: the distance to the nearest
+//   center the power doesn't own, then the size of the power owning it
+fn target_score(
+    game: &Stpsyr,
+    power: &Power,
+    province: &Province,
+    unit_type: UnitType,
+) -> Option<(usize, u32)> {
+    let sc_counts = game.sc_counts();
+    let distances = game.distances(province, unit_type);
+    game.map
+        .iter()
+        .filter(|r| r.sc && r.owner.as_ref() != Some(power))
+        .filter_map(|r| {
+            distances.get(&r.province.name).map(|&d| {
+                (
+                    d,
+                    r.owner
+                        .as_ref()
+                        .and_then(|o| sc_counts.get(o).cloned())
+                        .unwrap_or(0),
+                )
+            })
+        })
+        .min()
+}
+
+// count a power's fleets and armies, including pending builds
+fn unit_type_counts(game: &Stpsyr, power: &Power) -> (usize, usize) {
+    let unit_types = game
+        .map
+        .iter()
+        .filter_map(|r| r.unit.as_ref())
+        .filter(|u| u.owner == *power)
+        .map(|u| u.unit_type)
+        .chain(game.adjusts.iter().filter_map(|a| match a.action {
+            AdjustAction::Build { unit_type, .. } if a.owner == *power => Some(unit_type),
+            _ => None,
+        }))
+        .collect::<Vec<UnitType>>();
+    let fleets = unit_types.iter().filter(|&&t| t == UnitType::Fleet).count();
+    (fleets, unit_types.len() - fleets)
+}
@@ -0,0 +1,131 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use stpsyr::types::*;
+
+// a Clock tells the time in seconds since the epoch; it's a trait so that
+//   tests can control time instead of waiting for it
+pub trait Clock {
+    fn now(&self) -> u64;
+}
+
+// the actual time
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+    }
+}
+
+// a clock that only moves when told to; clones share the same time, so one
+//   can be handed to a game or server while another is used to move it
+#[derive(Clone)]
+pub struct ManualClock {
+    now: Rc<Cell<u64>>,
+}
+
+impl ManualClock {
+    pub fn new(now: u64) -> ManualClock {
+        ManualClock {
+            now: Rc::new(Cell::new(now)),
+        }
+    }
+
+    pub fn advance(&self, seconds: u64) {
+        self.now.set(self.now.get() + seconds);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.now.get()
+    }
+}
+
+impl Stpsyr {
+    // the powers that have something to order in the current phase: units
+    //   to move, units to retreat, or builds or disbands to make
+    pub fn powers_to_order(&self) -> Vec<Power> {
+        let mut powers = vec![];
+        match self.phase {
+            Phase::SpringDiplomacy | Phase::FallDiplomacy => {
+                for r in &self.map {
+                    if let Some(ref unit) = r.unit {
+                        if !powers.contains(&unit.owner) {
+                            powers.push(unit.owner.clone());
+                        }
+                    }
+                }
+            }
+            Phase::SpringRetreats | Phase::FallRetreats => {
+                for (_, unit) in &self.dislodged {
+                    if !powers.contains(&unit.owner) {
+                        powers.push(unit.owner.clone());
+                    }
+                }
+            }
+            Phase::Builds => {
+                for (power, delta) in self.adjust_deltas() {
+                    if delta != 0 {
+                        powers.push(power);
+                    }
+                }
+            }
+        }
+        powers
+    }
+
+    // mark a power as done (or not done after all) with its orders
+    pub fn set_ready(&mut self, power: &Power, ready: bool) {
+        self.ready.retain(|p| p != power);
+        if ready {
+            self.ready.push(power.clone());
+        }
+    }
+
+    // whether every power with something to order is ready
+    pub fn all_ready(&self) -> bool {
+        self.powers_to_order()
+            .iter()
+            .all(|p| self.ready.contains(p))
+    }
+
+    // check the time: start the current phase's deadline if it hasn't been
+    //   started yet, and adjudicate the phase if everyone's ready or the
+    //   deadline has passed, with NMRs handled as usual; returns whether the
+    //   phase was adjudicated
+    pub fn tick(&mut self, clock: &dyn Clock) -> bool {
+        if self.is_due(clock) {
+            self.apply();
+            // the next phase starts now
+            self.start_deadline(clock.now());
+            true
+        } else {
+            false
+        }
+    }
+
+    // whether the current phase is over, i.e. whether tick would adjudicate
+    //   it; this starts the phase's deadline if it hasn't been already
+    pub fn is_due(&mut self, clock: &dyn Clock) -> bool {
+        let now = clock.now();
+        self.start_deadline(now);
+        self.all_ready() || self.deadline.is_some_and(|d| now >= d)
+    }
+
+    fn start_deadline(&mut self, now: u64) {
+        if self.deadline.is_some() {
+            return;
+        }
+        let length = match self.phase {
+            Phase::SpringDiplomacy | Phase::FallDiplomacy => self.phase_lengths.diplomacy,
+            Phase::SpringRetreats | Phase::FallRetreats => self.phase_lengths.retreats,
+            Phase::Builds => self.phase_lengths.builds,
+        };
+        self.deadline = length.map(|l| now + l);
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+
+use stpsyr::types::*;
+
+// something that happened in a game that players watching it would want to
+//   know about right away; as JSON, the kind of event is in a "type" field,
+//   e.g. {"type": "order-submitted", "power": {"name": "Italy"}, ...}
+#[derive(Serialize, Clone)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Event {
+    // a power entered orders for the current phase; what they are stays
+    //   private until the phase is adjudicated
+    OrderSubmitted {
+        power: Power,
+        phase: Phase,
+        year: i32,
+    },
+    // a phase was resolved: results has the resolved orders if it was a
+    //   diplomacy phase, and state is the position that followed (without
+    //   its history)
+    PhaseAdjudicated {
+        phase: Phase,
+        year: i32,
+        results: Option<PhaseRecord>,
+        state: Box<Stpsyr>,
+    },
+    // these units were dislodged and have to retreat or disband
+    RetreatRequired {
+        phase: Phase,
+        year: i32,
+        dislodged: Vec<(Province, Unit)>,
+    },
+    // each power's builds (positive) or disbands (negative), by name
+    BuildsRequired {
+        year: i32,
+        deltas: BTreeMap<String, i32>,
+    },
+    // a power has a solo
+    GameOver {
+        winner: Power,
+        year: i32,
+    },
+}
+
+impl Event {
+    // the event as it may be shown to a player (or to anyone, with no
+    //   viewer), leaving out what Stpsyr::redacted would
+    pub fn redacted(&self, viewer: Option<&Power>) -> Event {
+        match *self {
+            Event::PhaseAdjudicated {
+                phase,
+                year,
+                ref results,
+                ref state,
+            } => Event::PhaseAdjudicated {
+                phase,
+                year,
+                results: results.clone(),
+                state: Box::new(state.redacted(viewer)),
+            },
+            ref event => event.clone(),
+        }
+    }
+}
+
+impl Stpsyr {
+    // the events that adjudicating the phase of the given position led to,
+    //   assuming this is the position right after that; nothing if the phase
+    //   hasn't been adjudicated yet
+    pub fn events_after(&self, before: &Stpsyr) -> Vec<Event> {
+        let (phase, year) = (before.phase, before.year);
+        if self.phase == phase && self.year == year {
+            return vec![];
+        }
+
+        let mut state = self.clone();
+        state.history.clear();
+        let mut events = vec![Event::PhaseAdjudicated {
+            phase,
+            year,
+            results: self
+                .history
+                .last()
+                .filter(|r| r.phase == phase && r.year == year)
+                .cloned(),
+            state: Box::new(state),
+        }];
+
+        match self.phase {
+            Phase::SpringRetreats | Phase::FallRetreats => events.push(Event::RetreatRequired {
+                phase: self.phase,
+                year: self.year,
+                dislodged: self.dislodged.clone(),
+            }),
+            Phase::Builds => events.push(Event::BuildsRequired {
+                year: self.year,
+                deltas: self
+                    .adjust_deltas()
+                    .into_iter()
+                    .filter(|&(_, d)| d != 0)
+                    .map(|(p, d)| (p.name, d))
+                    .collect(),
+            }),
+            Phase::SpringDiplomacy | Phase::FallDiplomacy => {}
+        }
+
+        // the game is only over once, when somebody first gets a solo
+        if let (Some(winner), None) = (self.solo_winner(), before.solo_winner()) {
+            events.push(Event::GameOver {
+                winner,
+                year: self.year,
+            });
+        }
+        events
+    }
+}
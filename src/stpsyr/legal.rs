@@ -0,0 +1,282 @@
+use std::collections::HashSet;
+
+use stpsyr::types::*;
+
+impl Stpsyr {
+    // list everything the unit (or, during builds, the supply center) in a
+    //   province may legally be ordered to do in the current phase
+    // candidates are generated from the map and then filtered through the
+    //   same checks that add_order, add_retreat and add_adjust use
+    pub fn legal_orders(&self, province: &Province) -> Vec<LegalOrder> {
+        match self.phase {
+            Phase::SpringDiplomacy | Phase::FallDiplomacy => self
+                .legal_diplomacy_orders(province)
+                .into_iter()
+                .map(LegalOrder::Order)
+                .collect(),
+            Phase::SpringRetreats | Phase::FallRetreats => self
+                .legal_retreats(province)
+                .into_iter()
+                .map(LegalOrder::Retreat)
+                .collect(),
+            Phase::Builds => self
+                .legal_adjusts(province)
+                .into_iter()
+                .map(LegalOrder::Adjust)
+                .collect(),
+        }
+    }
+
+    // list the provinces a power can give orders for in the current phase
+    pub fn orderable_provinces(&self, power: &Power) -> Vec<Province> {
+        match self.phase {
+            Phase::SpringDiplomacy | Phase::FallDiplomacy => self
+                .map
+                .iter()
+                .filter(|r| r.unit.as_ref().is_some_and(|u| u.owner == *power))
+                .map(|r| r.province.clone())
+                .collect(),
+            Phase::SpringRetreats | Phase::FallRetreats => self
+                .dislodged
+                .iter()
+                .filter(|(_, u)| u.owner == *power)
+                .map(|(p, _)| p.clone())
+                .collect(),
+            Phase::Builds => self
+                .map
+                .iter()
+                .filter(|r| match r.unit {
+                    Some(ref u) => u.owner == *power,
+                    None => {
+                        r.sc && r.home_power.as_ref() == Some(power)
+                            && r.owner.as_ref() == Some(power)
+                    }
+                })
+                .map(|r| r.province.clone())
+                .collect(),
+        }
+    }
+
+    fn legal_diplomacy_orders(&self, province: &Province) -> Vec<Action> {
+        let unit = if let Some(unit) = self.get_unit(province) {
+            unit
+        } else {
+            return vec![];
+        };
+        let region = self.get_region(province).unwrap();
+
+        let mut actions = vec![Action::Hold];
+
+        // moves over land or water
+        for to in self.move_destinations(region, unit.unit_type) {
+            actions.push(Action::Move {
+                to,
+                convoyed: false,
+            });
+        }
+
+        // moves by convoy
+        if unit.unit_type == UnitType::Army {
+            for to in self.convoy_reach(province).1 {
+                actions.push(Action::Move { to, convoyed: true });
+            }
+        }
+
+        // supports, which only need to reach the province (not the coast)
+        let supportable: Vec<Province> = self
+            .move_destinations(region, unit.unit_type)
+            .into_iter()
+            .map(|p| Province::from(p.name))
+            .collect();
+        for r in &self.map {
+            if r.province == *province || !supportable.contains(&r.province) {
+                continue;
+            }
+            if r.unit.is_some() {
+                actions.push(Action::SupportHold {
+                    to: Province::from(r.province.name.clone()),
+                });
+            }
+        }
+        for r in &self.map {
+            if r.province == *province {
+                continue;
+            }
+            let other = if let Some(ref other) = r.unit {
+                other
+            } else {
+                continue;
+            };
+            let mut reach: Vec<Province> = self
+                .move_destinations(r, other.unit_type)
+                .into_iter()
+                .map(|p| Province::from(p.name))
+                .collect();
+            if other.unit_type == UnitType::Army {
+                reach.extend(self.convoy_reach(&r.province).1);
+            }
+            let mut seen = HashSet::new();
+            for to in reach {
+                if supportable.contains(&to) && seen.insert(to.clone()) {
+                    actions.push(Action::SupportMove {
+                        from: Province::from(r.province.name.clone()),
+                        to,
+                    });
+                }
+            }
+        }
+
+        // convoys, which can only be given by fleets on open water that are
+        //   part of a chain reaching the army
+        if unit.unit_type == UnitType::Fleet && is_sea(region) {
+            for r in &self.map {
+                if !r
+                    .unit
+                    .as_ref()
+                    .is_some_and(|u| u.unit_type == UnitType::Army)
+                {
+                    continue;
+                }
+                let (seas, destinations) = self.convoy_reach(&r.province);
+                if !seas.contains(province) {
+                    continue;
+                }
+                for to in destinations {
+                    actions.push(Action::Convoy {
+                        from: Province::from(r.province.name.clone()),
+                        to,
+                    });
+                }
+            }
+        }
+
+        actions
+            .into_iter()
+            .filter(|action| self.order_is_legal(&unit.owner, province, action))
+            .collect()
+    }
+
+    fn legal_retreats(&self, province: &Province) -> Vec<RetreatAction> {
+        let unit = if let Some((_, unit)) = self.dislodged.iter().find(|(p, _)| p == province) {
+            unit
+        } else {
+            return vec![];
+        };
+        let region = self.get_region(province).unwrap();
+
+        let mut actions = vec![RetreatAction::Disband];
+        for to in self.move_destinations(region, unit.unit_type) {
+            actions.push(RetreatAction::Move { to });
+        }
+
+        actions
+            .into_iter()
+            .filter(|action| self.retreat_is_legal(&unit.owner, province, action))
+            .collect()
+    }
+
+    fn legal_adjusts(&self, province: &Province) -> Vec<AdjustAction> {
+        let region = if let Some(region) = self.get_region(province) {
+            region
+        } else {
+            return vec![];
+        };
+
+        // a unit may be disbanded by its owner, an empty home center may be
+        //   built in by its home power
+        if let Some(ref unit) = region.unit {
+            vec![AdjustAction::Disband]
+                .into_iter()
+                .filter(|action| self.adjust_is_legal(&unit.owner, province, action))
+                .collect()
+        } else if let Some(ref home_power) = region.home_power {
+            // a fleet in a split province is built on one of its coasts
+            let coasts = region.coasts();
+            let fleet_coasts = if coasts.is_empty() {
+                vec![None]
+            } else {
+                coasts.into_iter().map(Some).collect()
+            };
+            let mut actions = vec![AdjustAction::Build {
+                unit_type: UnitType::Army,
+                coast: None,
+            }];
+            actions.extend(fleet_coasts.into_iter().map(|coast| AdjustAction::Build {
+                unit_type: UnitType::Fleet,
+                coast,
+            }));
+            actions
+                .into_iter()
+                .filter(|action| self.adjust_is_legal(home_power, province, action))
+                .collect()
+        } else {
+            vec![]
+        }
+    }
+
+    // every province (with a coast, for fleets) directly reachable by a unit
+    //   of the given type in a region
+    fn move_destinations(&self, region: &MapRegion, unit_type: UnitType) -> Vec<Province> {
+        match unit_type {
+            UnitType::Army => region.army_borders.clone(),
+            UnitType::Fleet => region
+                .fleet_borders
+                .iter()
+                .filter(|p| p.from_coast == region.province.coast)
+                .map(|p| Province {
+                    name: p.name.clone(),
+                    coast: p.coast,
+                    from_coast: None,
+                })
+                .collect(),
+        }
+    }
+
+    // find the fleet-occupied seas connected to a province and every coastal
+    //   province an army there could be convoyed to through them
+    pub fn convoy_reach(&self, province: &Province) -> (Vec<Province>, Vec<Province>) {
+        let start = if let Some(region) = self.get_region(province) {
+            region
+        } else {
+            return (vec![], vec![]);
+        };
+
+        let mut seas: Vec<&MapRegion> = vec![];
+        let mut frontier = vec![start];
+        while let Some(region) = frontier.pop() {
+            for r in &self.map {
+                if is_sea(r)
+                    && r.unit.is_some()
+                    && region.fleet_borders.contains(&r.province)
+                    && !seas.contains(&r)
+                {
+                    seas.push(r);
+                    frontier.push(r);
+                }
+            }
+        }
+
+        let mut destinations: Vec<Province> = vec![];
+        for sea in &seas {
+            for p in &sea.fleet_borders {
+                let dest = Province::from(p.name.clone());
+                if dest != *province
+                    && !destinations.contains(&dest)
+                    && self.get_region(&dest).is_some_and(|r| !is_sea(r))
+                {
+                    destinations.push(dest);
+                }
+            }
+        }
+
+        (
+            seas.iter().map(|r| r.province.clone()).collect(),
+            destinations,
+        )
+    }
+}
+
+// it's open water if a fleet can go there but an army can't
+fn is_sea(region: &MapRegion) -> bool {
+    region.army_borders.is_empty() && !region.fleet_borders.is_empty()
+}
@@ -3,16 +3,31 @@ extern crate csv;
 
 use std::collections::HashSet;
 
+pub mod bots;
+pub mod deadlines;
+pub mod events;
+pub mod observer;
+pub mod pbem;
 pub mod rendering;
+pub mod repl;
+pub mod server;
+pub mod storage;
+pub mod tournament;
 mod types;
 mod json;
 pub use self::types::*;
 mod adjudicate;
 mod adjusts;
+mod legal;
+mod nmr;
 mod orders;
 mod parse;
+mod press;
+mod redact;
 mod retreats;
+mod summary;
 mod util;
+mod validate;
 
 impl Stpsyr {
     pub fn new(mapfile: &str) -> Stpsyr {
@@ -82,12 +97,22 @@ impl Stpsyr {
             adjusts: vec![],
             dependencies: vec![],
             dislodged: vec![],
+            attacked_from: vec![],
             contested: HashSet::new(),
             phase: Phase::SpringDiplomacy,
             year: 1901,
+            history: vec![],
+            nmr_log: vec![],
+            nmr_policy: NmrPolicy::HoldAll,
+            phase_lengths: PhaseLengths::default(),
+            deadline: None,
+            ready: vec![],
+            press: vec![],
+            press_rules: PressRules::default(),
+            observers: observer::Observers::default(),
         }
     }
-    pub fn parse_json(json: &str) -> Stpsyr{
-        serde_json::from_str(json).unwrap()
+    pub fn parse_json(json: &str) -> serde_json::Result<Stpsyr> {
+        serde_json::from_str(json)
     }
 }
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+use stpsyr::types::*;
+
+impl Stpsyr {
+    // the powers that have something to order but haven't had a single
+    //   order (or retreat, or build or disband) accepted yet this phase
+    pub fn nmrs(&self) -> Vec<Power> {
+        self.powers_to_order()
+            .into_iter()
+            .filter(|p| match self.phase {
+                Phase::SpringDiplomacy | Phase::FallDiplomacy => {
+                    !self.orders.iter().any(|o| o.owner == *p)
+                }
+                Phase::SpringRetreats | Phase::FallRetreats => {
+                    !self.retreats.iter().any(|r| r.owner == *p)
+                }
+                Phase::Builds => !self.adjusts.iter().any(|a| a.owner == *p),
+            })
+            .collect()
+    }
+
+    // log the powers that missed the current retreat or builds phase
+    pub fn record_nmrs(&mut self) {
+        for power in self.nmrs() {
+            info!(
+                phase:% = self.phase, year = self.year, power = &power.name[..];
+                "no orders received"
+            );
+            self.nmr_log.push((self.phase, self.year, power));
+        }
+    }
+
+    // count how many phases of any kind each power has missed so far
+    pub fn nmr_counts(&self) -> HashMap<Power, u32> {
+        let mut counts = HashMap::new();
+        for record in &self.history {
+            for power in &record.nmrs {
+                *counts.entry(power.clone()).or_insert(0) += 1;
+            }
+        }
+        for (_, _, power) in &self.nmr_log {
+            *counts.entry(power.clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    // a power that didn't disband as many units as it had to is in civil
+    //   disorder, and loses its units farthest from its home centers first;
+    //   ties go to fleets before armies, then alphabetically
+    pub fn civil_disorder_disbands(&self) -> Vec<Adjust> {
+        let mut disbands = vec![];
+        for (power, delta) in self.adjust_deltas() {
+            let ordered = self
+                .adjusts
+                .iter()
+                .filter(|a| a.owner == power && matches!(a.action, AdjustAction::Disband))
+                .count() as i32;
+            if delta + ordered >= 0 {
+                continue;
+            }
+
+            let homes: Vec<&str> = self
+                .map
+                .iter()
+                .filter(|r| r.sc && r.home_power.as_ref() == Some(&power))
+                .map(|r| &r.province.name[..])
+                .collect();
+            let mut units: Vec<(usize, bool, &Province)> = self
+                .map
+                .iter()
+                .filter(|r| !self.adjusts.iter().any(|a| a.province == r.province))
+                .filter_map(|r| {
+                    let unit = r.unit.as_ref().filter(|u| u.owner == power)?;
+                    let distances = self.distances(&r.province, unit.unit_type);
+                    let distance = homes
+                        .iter()
+                        .filter_map(|home| distances.get(*home))
+                        .min()
+                        .cloned()
+                        .unwrap_or(usize::MAX);
+                    Some((distance, unit.unit_type == UnitType::Army, &r.province))
+                })
+                .collect();
+            units.sort_by(|a, b| {
+                b.0.cmp(&a.0)
+                    .then(a.1.cmp(&b.1))
+                    .then(a.2.name.cmp(&b.2.name))
+            });
+
+            for (_, _, province) in units.into_iter().take(-(delta + ordered) as usize) {
+                disbands.push(Adjust {
+                    owner: power.clone(),
+                    province: province.clone(),
+                    action: AdjustAction::Disband,
+                });
+            }
+        }
+        disbands
+    }
+
+    // give default orders to powers that didn't submit any
+    // holding is what happens to a unit without an order anyway, so only
+    //   RepeatLast actually has to add anything
+    pub fn apply_nmr_policy(&mut self, nmrs: &[Power]) {
+        if self.nmr_policy != NmrPolicy::RepeatLast {
+            return;
+        }
+
+        let last_orders = if let Some(record) = self.history.last() {
+            record.orders.clone()
+        } else {
+            return;
+        };
+        for order in last_orders {
+            if !nmrs.contains(&order.owner) {
+                continue;
+            }
+            match order.action {
+                Action::Hold | Action::SupportHold { .. } | Action::SupportMove { .. } => {
+                    // add_order takes care of units that aren't there any more
+                    self.add_order(order.owner, order.province, order.action);
+                }
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::sync::{Arc, Mutex};
+
+use stpsyr::types::*;
+
+// something the engine did while adjudicating, in the order it happened
+#[derive(Clone)]
+pub enum EngineEvent {
+    // an order was resolved; order.resolution says whether it succeeded
+    OrderResolved {
+        order: Order,
+    },
+    // a unit was dislodged and will have to retreat (or disband)
+    UnitDislodged {
+        province: Province,
+        unit: Unit,
+    },
+    UnitRetreated {
+        from: Province,
+        to: Province,
+        unit: Unit,
+    },
+    // a unit was removed, whether it was ordered to disband, had nowhere to
+    //   retreat to or bounced while retreating
+    UnitDisbanded {
+        province: Province,
+        unit: Unit,
+    },
+    // a supply center changed hands; from is None if it was neutral
+    ScCaptured {
+        province: Province,
+        from: Option<Power>,
+        to: Power,
+    },
+    UnitBuilt {
+        province: Province,
+        unit: Unit,
+    },
+    PhaseChanged {
+        from: (Phase, i32),
+        to: (Phase, i32),
+    },
+}
+
+// an Observer is told about everything the engine does to a game it's
+//   watching, e.g. to keep statistics or send notifications
+pub trait Observer {
+    fn notify(&mut self, event: &EngineEvent);
+}
+
+// the observers watching a game; they're shared so that whoever added one
+//   can still look at it afterwards, and Send so that a watched game can be
+//   moved to another thread
+#[derive(Default)]
+pub struct Observers(Vec<Arc<Mutex<dyn Observer + Send>>>);
+
+// a copy of a game (e.g. a fork for what-if analysis) isn't the game that's
+//   being watched, so it starts without any observers
+impl Clone for Observers {
+    fn clone(&self) -> Observers {
+        Observers::default()
+    }
+}
+
+impl Stpsyr {
+    // have an observer told about everything that happens from now on, e.g.
+    //   with game.add_observer(stats.clone()) for an Arc<Mutex<Stats>>
+    pub fn add_observer(&mut self, observer: Arc<Mutex<dyn Observer + Send>>) {
+        self.observers.0.push(observer);
+    }
+
+    // tell every observer about an event
+    pub fn notify(&self, event: EngineEvent) {
+        // an observer that panicked once is still told about later events
+        for observer in &self.observers.0 {
+            observer
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .notify(&event);
+        }
+    }
+}
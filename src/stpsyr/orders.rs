@@ -1,63 +1,106 @@
+use stpsyr::observer::EngineEvent;
 use stpsyr::types::*;
 
 impl Stpsyr {
-    // the publicly exposed function to modify self.orders
-    pub fn add_order(&mut self, owner: Power, province: Province, action: Action) {
+    // the publicly exposed function to modify self.orders; returns whether
+    //   the order was accepted
+    // an order for a unit that already has one replaces it, so players can
+    //   change their minds
+    pub fn add_order(&mut self, owner: Power, province: Province, action: Action) -> bool {
         match self.phase {
             Phase::SpringDiplomacy | Phase::FallDiplomacy => {}
             _ => panic!("add_order called during non-diplomacy phase"),
         };
 
+        if !self.order_is_legal(&owner, &province, &action) {
+            return false;
+        }
+
+        // all checks pass
+        if let Some(existing) = self.orders.iter_mut().find(|o| o.province == province) {
+            existing.province = province;
+            existing.action = action;
+            return true;
+        }
+        let id = self.orders.len();
+        self.orders.push(Order {
+            owner,
+            province,
+            action,
+            resolution: false,
+            state: OrderState::UNRESOLVED,
+            id,
+        });
+        true
+    }
+
+    // the checks behind add_order, also used to generate legal orders
+    pub fn order_is_legal(&self, owner: &Power, province: &Province, action: &Action) -> bool {
         // there has to be a unit here to order it
-        let unit = if let Some(unit) = self.get_unit(&province) {
+        let unit = if let Some(unit) = self.get_unit(province) {
             unit
         } else {
-            return;
+            return false;
         };
 
-        let (is_move, convoyed) = match action {
+        let (is_move, convoyed) = match *action {
             Action::Move { ref to, convoyed } => {
                 // let's do a quick check here: unit can't move to itself
-                if province == *to {
-                    return;
+                if *province == *to {
+                    return false;
                 }
                 (true, convoyed)
             }
             Action::SupportMove { ref from, ref to } => {
                 // another quick check: can't support yourself or a non-move
-                if province == *from || province == *to || *from == *to {
-                    return;
+                if *province == *from || *province == *to || *from == *to {
+                    return false;
                 }
                 (false, false)
             }
             _ => (false, false),
         }; // NOTE use this better
 
+        // everything an order refers to has to be on the map
+        match *action {
+            Action::Move { ref to, .. } | Action::SupportHold { ref to } => {
+                if self.get_region(to).is_none() {
+                    return false;
+                }
+            }
+            Action::SupportMove { ref from, ref to } | Action::Convoy { ref from, ref to } => {
+                if self.get_region(from).is_none() || self.get_region(to).is_none() {
+                    return false;
+                }
+            }
+            Action::Hold => {}
+        }
+
         // TODO ensure convoying fleets are on open water
         // TODO ensure convoying armies actually have the requisite fleets
         // (c.f. DATC 6.D.32)
 
         // can't convoy a fleet
         if convoyed && unit.unit_type == UnitType::Fleet {
-            return;
+            return false;
         }
 
         // can't order a unit that's not yours
-        if unit.owner != owner {
-            return;
+        if unit.owner != *owner {
+            return false;
         }
 
         // can't order to a province you can't reach
-        if !convoyed
-            && match &action {
-                &Action::Move {
+        convoyed
+            || match *action {
+                Action::Move {
                     ref to,
                     convoyed: _,
                 }
-                | &Action::SupportHold { ref to }
-                | &Action::SupportMove { from: _, ref to } => {
-                    let r = self.get_region(&province).unwrap();
-                    !match unit.unit_type {
+                | Action::SupportHold { ref to }
+                | Action::SupportMove { from: _, ref to } => {
+                    let r = self.get_region(province).unwrap();
+                    match unit.unit_type {
                         UnitType::Army => r.army_borders.clone(),
                         UnitType::Fleet => r
                             .fleet_borders
@@ -71,40 +114,57 @@ impl Stpsyr {
                     }
                     .contains(to)
                 }
-                _ => false,
+                _ => true,
             }
-        {
-            return;
-        }
-
-        // all checks pass
-        let id = self.orders.len();
-        self.orders.push(Order {
-            owner,
-            province,
-            action,
-            resolution: false,
-            state: OrderState::UNRESOLVED,
-            id,
-        });
     }
 
     // this is the publicly exposed function that is called once all orders
     //   have been added
     // TODO support retreats and builds
-    // TODO clear self.contested
     pub fn apply_orders(&mut self) {
+        // standoffs from earlier phases don't matter anymore
+        self.contested.clear();
+
+        // fill in default orders for anyone who didn't submit
+        let nmrs = self.nmrs();
+        for power in &nmrs {
+            info!(
+                phase:% = self.phase, year = self.year, power = &power.name[..];
+                "no orders received"
+            );
+        }
+        self.apply_nmr_policy(&nmrs);
+
         // resolve all orders
         for i in 0..self.orders.len() {
             self.resolve(i);
             assert!(self.orders[i].state == OrderState::RESOLVED);
-            println!("{:?}", self.orders[i]);
+            debug!(
+                phase:% = self.phase, year = self.year;
+                "{} {}",
+                self.orders[i],
+                if self.orders[i].resolution { "succeeded" } else { "failed" }
+            );
+            self.notify(EngineEvent::OrderResolved {
+                order: self.orders[i].clone(),
+            });
         }
 
+        self.history.push(PhaseRecord {
+            phase: self.phase,
+            year: self.year,
+            orders: self.orders.clone(),
+            nmrs,
+        });
+
         // do the moves that were successfully resolved
         self.apply_resolved();
-
-        // println!("{:?} {}: {:?}", self.phase, self.year, self.map);
+        for (province, unit) in &self.dislodged {
+            self.notify(EngineEvent::UnitDislodged {
+                province: province.clone(),
+                unit: unit.clone(),
+            });
+        }
 
         self.next_phase();
         self.orders = vec![];
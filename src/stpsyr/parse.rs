@@ -3,8 +3,9 @@ use stpsyr::types::*;
 extern crate bincode;
 
 impl Stpsyr {
-    // parse orders as a string and apply them
-    pub fn parse(&mut self, power: &Power, orders: String) {
+    // parse orders as a string and apply them, one per line; returns how many
+    //   were accepted
+    pub fn parse(&mut self, power: &Power, orders: String) -> usize {
         match self.phase {
             Phase::SpringDiplomacy | Phase::FallDiplomacy => self.parse_orders(power, orders),
             Phase::SpringRetreats | Phase::FallRetreats => self.parse_retreats(power, orders),
@@ -20,6 +21,77 @@ impl Stpsyr {
         }
     }
 
+    // add a single structured order, e.g. one picked from legal_orders();
+    //   returns whether it was accepted
+    pub fn submit(&mut self, power: &Power, province: Province, order: LegalOrder) -> bool {
+        match order {
+            LegalOrder::Order(action) => self.add_order(power.clone(), province, action),
+            LegalOrder::Retreat(action) => self.add_retreat(power.clone(), province, action),
+            LegalOrder::Adjust(action) => self.add_adjust(power.clone(), province, action),
+        }
+    }
+
+    // a power's orders for the current phase so far, in the usual short
+    //   notation
+    pub fn pending_orders(&self, power: &Power) -> Vec<String> {
+        match self.phase {
+            Phase::SpringDiplomacy | Phase::FallDiplomacy => self
+                .orders
+                .iter()
+                .filter(|o| o.owner == *power)
+                .map(|o| o.to_string())
+                .collect(),
+            Phase::SpringRetreats | Phase::FallRetreats => self
+                .retreats
+                .iter()
+                .filter(|r| r.owner == *power)
+                .map(|r| r.to_string())
+                .collect(),
+            Phase::Builds => self
+                .adjusts
+                .iter()
+                .filter(|a| a.owner == *power)
+                .map(|a| a.to_string())
+                .collect(),
+        }
+    }
+
+    // take back all of a power's orders for the current phase
+    pub fn clear_orders(&mut self, power: &Power) {
+        self.orders.retain(|o| o.owner != *power);
+        self.retreats.retain(|r| r.owner != *power);
+        self.adjusts.retain(|a| a.owner != *power);
+        // orders are looked up by id while adjudicating
+        for (id, order) in self.orders.iter_mut().enumerate() {
+            order.id = id;
+        }
+    }
+
+    // copy the current position so that it can be played out independently
+    //   of this one (and without telling this one's observers about it)
+    pub fn fork(&self) -> Stpsyr {
+        self.clone()
+    }
+
+    // go back to an earlier copy of this game (e.g. one from fork()), still
+    //   watched by this game's observers
+    pub fn restore(&mut self, earlier: Stpsyr) {
+        let observers = ::std::mem::take(&mut self.observers);
+        *self = earlier;
+        self.observers = observers;
+    }
+
+    // adjudicate hypothetical orders for any number of powers on a fork of
+    //   the current position and return the result, leaving self untouched
+    pub fn what_if(&self, orders: &[(Power, String)]) -> Stpsyr {
+        let mut fork = self.fork();
+        for (power, power_orders) in orders {
+            fork.parse(power, power_orders.clone());
+        }
+        fork.apply();
+        fork
+    }
+
     pub fn serialize(&self) -> Vec<u8> {
         bincode::serialize(self).unwrap()
     }
@@ -28,7 +100,8 @@ impl Stpsyr {
         bincode::deserialize(encoded).unwrap()
     }
 
-    fn parse_orders(&mut self, power: &Power, orders: String) {
+    fn parse_orders(&mut self, power: &Power, orders: String) -> usize {
+        let mut accepted = 0;
         for line in orders.lines() {
             let line = line.to_lowercase().replace('(', "/").replace(" /", "/");
             let tokens: Vec<&str> = line
@@ -62,13 +135,20 @@ impl Stpsyr {
                 continue;
             };
 
-            match tokens_iter.next() {
-                None => {} // hold
+            let ok = match tokens_iter.next() {
+                None => {
+                    // hold
+                    self.add_order(power.clone(), province, Action::Hold)
+                }
                 Some(token2) => {
                     match token2 {
                         "s" => {
                             // support
-                            let a = tokens_iter.next().unwrap();
+                            let a = if let Some(a) = tokens_iter.next() {
+                                a
+                            } else {
+                                continue;
+                            };
                             if let Some(b) = tokens_iter.next() {
                                 // support move
                                 self.add_order(
@@ -78,7 +158,7 @@ impl Stpsyr {
                                         from: Province::from(a),
                                         to: Province::from(b),
                                     },
-                                );
+                                )
                             } else {
                                 // support hold
                                 self.add_order(
@@ -87,13 +167,18 @@ impl Stpsyr {
                                     Action::SupportHold {
                                         to: Province::from(a),
                                     },
-                                );
+                                )
                             }
                         }
                         "c" => {
                             // convoy
-                            let from = tokens_iter.next().unwrap();
-                            let to = tokens_iter.next().unwrap();
+                            let (from, to) = if let (Some(from), Some(to)) =
+                                (tokens_iter.next(), tokens_iter.next())
+                            {
+                                (from, to)
+                            } else {
+                                continue;
+                            };
                             self.add_order(
                                 power.clone(),
                                 province,
@@ -101,7 +186,7 @@ impl Stpsyr {
                                     from: Province::from(from),
                                     to: Province::from(to),
                                 },
-                            );
+                            )
                         }
                         _ => {
                             // regular move
@@ -113,15 +198,20 @@ impl Stpsyr {
                                     to: Province::from(token2),
                                     convoyed: vc,
                                 },
-                            );
+                            )
                         }
                     }
                 }
+            };
+            if ok {
+                accepted += 1;
             }
         }
+        accepted
     }
 
-    fn parse_retreats(&mut self, power: &Power, orders: String) {
+    fn parse_retreats(&mut self, power: &Power, orders: String) -> usize {
+        let mut accepted = 0;
         for line in orders.lines() {
             let line = line
                 .to_lowercase()
@@ -151,7 +241,7 @@ impl Stpsyr {
                 continue;
             };
 
-            if let Some(p2) = tokens_iter.next() {
+            let ok = if let Some(p2) = tokens_iter.next() {
                 if tokens_iter.next().is_some() {
                     continue;
                 }
@@ -161,14 +251,19 @@ impl Stpsyr {
                     RetreatAction::Move {
                         to: Province::from(*p2),
                     },
-                );
+                )
             } else {
-                self.add_retreat(power.clone(), p1, RetreatAction::Disband);
+                self.add_retreat(power.clone(), p1, RetreatAction::Disband)
+            };
+            if ok {
+                accepted += 1;
             }
         }
+        accepted
     }
 
-    fn parse_adjusts(&mut self, power: &Power, orders: String) {
+    fn parse_adjusts(&mut self, power: &Power, orders: String) -> usize {
+        let mut accepted = 0;
         for line in orders.lines() {
             let line = line
                 .to_lowercase()
@@ -193,10 +288,12 @@ impl Stpsyr {
                     _ => token,
                 });
 
-            match tokens_iter.next() {
+            let ok = match tokens_iter.next() {
                 Some("d") => {
                     if let Some(p) = tokens_iter.next() {
-                        self.add_adjust(power.clone(), Province::from(p), AdjustAction::Disband);
+                        self.add_adjust(power.clone(), Province::from(p), AdjustAction::Disband)
+                    } else {
+                        false
                     }
                 }
                 Some("a") => {
@@ -206,23 +303,35 @@ impl Stpsyr {
                             Province::from(p),
                             AdjustAction::Build {
                                 unit_type: UnitType::Army,
+                                coast: None,
                             },
-                        );
+                        )
+                    } else {
+                        false
                     }
                 }
                 Some("f") => {
                     if let Some(p) = tokens_iter.next() {
+                        let province = Province::from(p);
+                        let coast = province.coast;
                         self.add_adjust(
                             power.clone(),
-                            Province::from(p),
+                            province,
                             AdjustAction::Build {
                                 unit_type: UnitType::Fleet,
+                                coast,
                             },
-                        );
+                        )
+                    } else {
+                        false
                     }
                 }
-                _ => {} // invalid
+                _ => false, // invalid
+            };
+            if ok {
+                accepted += 1;
             }
         }
+        accepted
     }
 }
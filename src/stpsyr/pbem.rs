@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use stpsyr::deadlines::Clock;
+use stpsyr::types::*;
+
+// a Judge runs a game by email (PBEM) without talking to a mail server:
+//   players' messages are delivered to a local maildir by whatever already
+//   receives the mail, and the replies are written to an outbox directory as
+//   one file per message, for whatever sends the mail to pick up
+//
+// a player's message is plain text with one order per line, e.g.
+//
+//   F nth C A lon - nwy
+//   A lon - nwy via convoy
+//   password swordfish
+//   signoff
+//
+// quoted lines (starting with ">") and blank lines are skipped, a line that
+//   is just "ready" marks the power as done for the phase, and a "signoff"
+//   line or a "-- " signature ends the orders; MIME messages are read from
+//   their text/plain part
+pub struct Judge {
+    pub game: Stpsyr,
+    // who's playing, keyed by their lowercase email address
+    pub players: HashMap<String, Player>,
+    // the address replies are sent from
+    pub address: String,
+    maildir: PathBuf,
+    outbox: PathBuf,
+}
+
+pub struct Player {
+    pub power: Power,
+    // if set, every message from this player has to contain a
+    //   "password <password>" line, since anyone can forge a From header
+    pub password: Option<String>,
+}
+
+// a message as read from the maildir or written to the outbox
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mail {
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Mail {
+    // read a message in the usual header-blank line-body format; the body is
+    //   kept as it is, see plain_text for getting the text out of it
+    pub fn parse(message: &str) -> Mail {
+        let (headers, body) = split(message);
+        Mail {
+            from: header(&headers, "From"),
+            to: header(&headers, "To"),
+            subject: header(&headers, "Subject"),
+            body,
+        }
+    }
+
+    // the text of a message: the body of a plain text message, or the first
+    //   text/plain part of a MIME one, decoded from quoted-printable if need
+    //   be; otherwise what's wrong with it, for telling the sender
+    pub fn plain_text(message: &str) -> Result<String, String> {
+        let (headers, body) = split(message);
+        let content_type = header(&headers, "Content-Type");
+        let mut params = content_type.split(';').map(str::trim);
+        let media_type = params.next().unwrap_or("").to_lowercase();
+
+        if media_type.starts_with("multipart/") {
+            let boundary = params
+                .filter_map(|param| param.split_once('='))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("boundary"))
+                .map(|(_, value)| value.trim().trim_matches('"').to_string())
+                .ok_or_else(|| String::from("your message's MIME parts couldn't be found"))?;
+            let (delimiter, end) = (format!("--{}", boundary), format!("--{}--", boundary));
+            // the parts are separated by delimiter lines, with anything before
+            //   the first one or after the end one ignored
+            let mut parts = vec![];
+            let mut part: Option<Vec<&str>> = None;
+            for line in body.lines() {
+                if line.trim_end() == delimiter || line.trim_end() == end {
+                    parts.extend(part.take());
+                    if line.trim_end() == end {
+                        break;
+                    }
+                    part = Some(vec![]);
+                } else if let Some(ref mut part) = part {
+                    part.push(line);
+                }
+            }
+            return parts
+                .iter()
+                .find_map(|part| Mail::plain_text(&part.join("\n")).ok())
+                .ok_or_else(|| {
+                    String::from("your message has no plain text part; please send your orders as plain text")
+                });
+        }
+        if !media_type.is_empty() && media_type != "text/plain" {
+            return Err(format!(
+                "your message is {}, which can't be read; please send your orders as plain text",
+                media_type
+            ));
+        }
+
+        let encoding = header(&headers, "Content-Transfer-Encoding").to_lowercase();
+        match &encoding[..] {
+            "" | "7bit" | "8bit" | "binary" => Ok(body),
+            "quoted-printable" => Ok(decode_quoted_printable(&body)),
+            _ => Err(format!(
+                "your message is encoded as {}, which can't be read; please send your orders as plain text",
+                encoding
+            )),
+        }
+    }
+
+    // the bare address out of e.g. "Alice <alice@example.com>"
+    pub fn address(header: &str) -> String {
+        let address = match (header.rfind('<'), header.rfind('>')) {
+            (Some(start), Some(end)) if start < end => &header[start + 1..end],
+            _ => header,
+        };
+        address.trim().to_lowercase()
+    }
+}
+
+impl std::fmt::Display for Mail {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "From: {}\nTo: {}\nSubject: {}\n\n{}",
+            self.from, self.to, self.subject, self.body
+        )
+    }
+}
+
+impl Judge {
+    pub fn new<P: AsRef<Path>, Q: AsRef<Path>>(game: Stpsyr, maildir: P, outbox: Q) -> Judge {
+        Judge {
+            game,
+            players: HashMap::new(),
+            address: String::from("judge@localhost"),
+            maildir: maildir.as_ref().to_path_buf(),
+            outbox: outbox.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn add_player(&mut self, address: &str, power: Power, password: Option<String>) {
+        self.players
+            .insert(Mail::address(address), Player { power, password });
+    }
+
+    // handle every message waiting in the maildir's new/ directory, oldest
+    //   (by file name) first, moving each one to cur/ once it's answered;
+    //   returns how many there were
+    pub fn check_mail(&mut self) -> io::Result<usize> {
+        let new = self.maildir.join("new");
+        let cur = self.maildir.join("cur");
+        fs::create_dir_all(&cur)?;
+
+        let mut names: Vec<_> = fs::read_dir(&new)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+            .map(|entry| entry.file_name())
+            .collect();
+        names.sort();
+
+        for name in &names {
+            let path = new.join(name);
+            let message = fs::read(&path)?;
+            let reply = self.handle_message(&String::from_utf8_lossy(&message));
+            self.send(&reply)?;
+            // ":2,S" is the maildir way of saying the message has been seen
+            let mut seen = name.clone();
+            seen.push(":2,S");
+            fs::rename(&path, cur.join(seen))?;
+        }
+        Ok(names.len())
+    }
+
+    // answer a message as read from the maildir, turning it away if its text
+    //   can't be read
+    pub fn handle_message(&mut self, message: &str) -> Mail {
+        let mail = Mail::parse(message);
+        match Mail::plain_text(message) {
+            Ok(body) => self.handle(&Mail { body, ..mail }),
+            Err(problem) => self.reply(&mail, format!("{}; no orders were entered\n", problem)),
+        }
+    }
+
+    // answer a single message: enter its orders for the sender's power and
+    //   report which were accepted
+    pub fn handle(&mut self, mail: &Mail) -> Mail {
+        let from = Mail::address(&mail.from);
+        let lines = order_lines(&mail.body);
+        let player = match self.players.get(&from) {
+            Some(player) => player,
+            None => return self.reply(mail, String::from("you aren't playing in this game\n")),
+        };
+        // the password is never echoed back, even when it's wrong
+        let given = lines.iter().find_map(|line| password(line));
+        if player.password.is_some() && player.password.as_deref() != given {
+            return self.reply(
+                mail,
+                String::from("wrong or missing password; no orders were entered\n"),
+            );
+        }
+
+        let power = player.power.clone();
+        let mut accepted = vec![];
+        let mut rejected = vec![];
+        let mut ready = false;
+        for line in lines.iter().filter(|line| password(line).is_none()) {
+            if line.eq_ignore_ascii_case("ready") {
+                ready = true;
+            } else if self.game.parse(&power, line.to_string()) > 0 {
+                accepted.push(line.clone());
+            } else {
+                rejected.push(line.clone());
+            }
+        }
+        if ready {
+            self.game.set_ready(&power, true);
+        }
+
+        let mut body = format!("{} {}, {}\n", self.game.phase, self.game.year, power.name);
+        if !accepted.is_empty() {
+            body.push_str(&format!("\naccepted:\n  {}\n", accepted.join("\n  ")));
+        }
+        if !rejected.is_empty() {
+            body.push_str(&format!("\nrejected:\n  {}\n", rejected.join("\n  ")));
+        }
+        let pending = self.game.pending_orders(&power);
+        if pending.is_empty() {
+            body.push_str("\nyou have no orders in yet\n");
+        } else {
+            body.push_str(&format!(
+                "\nyour orders so far:\n  {}\n",
+                pending.join("\n  ")
+            ));
+        }
+        if self.game.ready.contains(&power) {
+            body.push_str("\nyou are marked as ready\n");
+        }
+        self.reply(mail, body)
+    }
+
+    // a reply to a message, from the judge
+    fn reply(&self, mail: &Mail, body: String) -> Mail {
+        let subject = if mail.subject.to_lowercase().starts_with("re:") {
+            mail.subject.clone()
+        } else {
+            format!("Re: {}", mail.subject)
+        };
+        Mail {
+            from: self.address.clone(),
+            to: Mail::address(&mail.from),
+            subject,
+            body,
+        }
+    }
+
+    // adjudicate the phase if everyone's ready or its deadline has passed,
+    //   and if so send the results to every player; returns whether it was
+    //   adjudicated
+    pub fn tick(&mut self, clock: &dyn Clock) -> io::Result<bool> {
+        let (phase, year) = (self.game.phase, self.game.year);
+        if !self.game.tick(clock) {
+            return Ok(false);
+        }
+
+        let mut body = String::new();
+        if let Some(record) = self
+            .game
+            .history
+            .last()
+            .filter(|r| r.phase == phase && r.year == year)
+        {
+            for order in &record.orders {
+                body.push_str(&format!(
+                    "{}: {}{}\n",
+                    order.owner.name,
+                    order,
+                    if order.resolution { "" } else { " (failed)" }
+                ));
+            }
+            for power in &record.nmrs {
+                body.push_str(&format!("{}: no orders received\n", power.name));
+            }
+            body.push('\n');
+        }
+        body.push_str(&self.game.summary());
+
+        let mut addresses: Vec<&String> = self.players.keys().collect();
+        addresses.sort();
+        for address in addresses {
+            self.send(&Mail {
+                from: self.address.clone(),
+                to: address.clone(),
+                subject: format!("{} {} results", phase, year),
+                body: body.clone(),
+            })?;
+        }
+        Ok(true)
+    }
+
+    // write a message to the outbox, numbered after whatever's already there
+    //   (so that sent messages can be deleted without reusing numbers for
+    //   new ones, as long as the newest stays)
+    fn send(&self, mail: &Mail) -> io::Result<()> {
+        fs::create_dir_all(&self.outbox)?;
+        let last = fs::read_dir(&self.outbox)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                name.strip_suffix(".eml")?.parse::<u64>().ok()
+            })
+            .max()
+            .unwrap_or(0);
+        fs::write(
+            self.outbox.join(format!("{:06}.eml", last + 1)),
+            mail.to_string(),
+        )
+    }
+}
+
+// the lines of a message body that could be orders or commands
+fn order_lines(body: &str) -> Vec<String> {
+    body.lines()
+        .take_while(|line| *line != "-- " && !line.trim().eq_ignore_ascii_case("signoff"))
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('>'))
+        .map(String::from)
+        .collect()
+}
+
+// the password on a "password <password>" line, whatever the case of the
+//   keyword
+fn password(line: &str) -> Option<&str> {
+    let mut words = line.splitn(2, char::is_whitespace);
+    if words.next()?.eq_ignore_ascii_case("password") {
+        Some(words.next().unwrap_or("").trim())
+    } else {
+        None
+    }
+}
+
+// split a message (or a MIME part) into its headers, with folded ones joined
+//   back onto one line, and its body
+fn split(message: &str) -> (Vec<String>, String) {
+    let message = message.replace("\r\n", "\n");
+    let (headers, body) = match message.find("\n\n") {
+        Some(i) => (&message[..i], &message[i + 2..]),
+        None => (&message[..], ""),
+    };
+
+    // long headers can be folded onto lines starting with whitespace
+    let mut unfolded: Vec<String> = vec![];
+    for line in headers.lines() {
+        match unfolded.last_mut() {
+            Some(last) if line.starts_with(' ') || line.starts_with('\t') => {
+                last.push(' ');
+                last.push_str(line.trim());
+            }
+            _ => unfolded.push(line.to_string()),
+        }
+    }
+    (unfolded, body.to_string())
+}
+
+// the value of a header, or "" if it's missing
+fn header(headers: &[String], name: &str) -> String {
+    headers
+        .iter()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+        .map_or(String::new(), |(_, value)| value.trim().to_string())
+}
+
+// undo quoted-printable encoding: "=XX" is a byte in hex and a "=" at the end
+//   of a line joins it to the next
+fn decode_quoted_printable(body: &str) -> String {
+    let mut bytes = vec![];
+    let mut soft_break = true;
+    for line in body.split('\n') {
+        if !soft_break {
+            bytes.push(b'\n');
+        }
+        let line = line.trim_end_matches([' ', '\t', '\r']);
+        soft_break = line.ends_with('=');
+        let line = line.strip_suffix('=').unwrap_or(line);
+        let mut rest = line.as_bytes();
+        while let Some((&byte, tail)) = rest.split_first() {
+            let hex = tail
+                .get(..2)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            match (byte, hex) {
+                (b'=', Some(decoded)) => {
+                    bytes.push(decoded);
+                    rest = &tail[2..];
+                }
+                _ => {
+                    bytes.push(byte);
+                    rest = tail;
+                }
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -0,0 +1,67 @@
+use stpsyr::types::*;
+
+impl Stpsyr {
+    // send a message from one power to others (or to everyone, if to is
+    //   empty), if the game's press rules allow it
+    pub fn send_press(
+        &mut self,
+        from: &Power,
+        to: &[Power],
+        body: &str,
+        anonymous: bool,
+    ) -> Result<(), PressError> {
+        let powers = self.powers();
+        if !powers.contains(from) || to.iter().any(|p| !powers.contains(p)) {
+            return Err(PressError::UnknownPower);
+        }
+        if body.trim().is_empty() {
+            return Err(PressError::EmptyMessage);
+        }
+        if to.is_empty() && !self.press_rules.broadcast {
+            return Err(PressError::NoBroadcastPress);
+        }
+        if !to.is_empty() && !self.press_rules.private {
+            return Err(PressError::NoPrivatePress);
+        }
+        if anonymous && !self.press_rules.gray {
+            return Err(PressError::NoGrayPress);
+        }
+
+        let mut recipients: Vec<Power> = vec![];
+        for power in to {
+            if !recipients.contains(power) {
+                recipients.push(power.clone());
+            }
+        }
+        self.press.push(Message {
+            from: Some(from.clone()),
+            to: recipients,
+            body: body.to_string(),
+            anonymous,
+            phase: self.phase,
+            year: self.year,
+        });
+        Ok(())
+    }
+
+    // the messages a power can read, oldest first: broadcasts, and messages
+    //   it sent or received; the senders of anonymous messages are left out
+    //   unless it sent them
+    // with no viewer, only broadcasts are shown
+    pub fn press_for(&self, viewer: Option<&Power>) -> Vec<Message> {
+        self.press
+            .iter()
+            .filter(|m| {
+                m.to.is_empty()
+                    || viewer.is_some_and(|v| m.from.as_ref() == Some(v) || m.to.contains(v))
+            })
+            .map(|m| {
+                let mut m = m.clone();
+                if m.anonymous && m.from.as_ref() != viewer {
+                    m.from = None;
+                }
+                m
+            })
+            .collect()
+    }
+}
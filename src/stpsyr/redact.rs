@@ -0,0 +1,24 @@
+use stpsyr::types::*;
+
+impl Stpsyr {
+    // a copy of the position that's safe to show to a player: the pending
+    //   orders, retreats and adjustments of every power other than viewer
+    //   are left out (all of them, if there's no viewer), as is any press
+    //   the viewer can't read, while everything already adjudicated stays
+    //   public
+    pub fn redacted(&self, viewer: Option<&Power>) -> Stpsyr {
+        let mut redacted = self.clone();
+        let visible = |owner: &Power| viewer == Some(owner);
+        redacted.orders.retain(|o| visible(&o.owner));
+        redacted.retreats.retain(|r| visible(&r.owner));
+        redacted.adjusts.retain(|a| visible(&a.owner));
+        redacted.press = self.press_for(viewer);
+        // dependencies refer to orders by index, and are only meaningful
+        //   while adjudicating anyway
+        redacted.dependencies.clear();
+        for (id, order) in redacted.orders.iter_mut().enumerate() {
+            order.id = id;
+        }
+        redacted
+    }
+}
@@ -1,41 +1,181 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fmt;
 use std::fs;
 use std::fs::File;
 use std::io::Write;
+use std::ops::Range;
 
 use stpsyr::types::*;
 
+// a RenderOptions describes how the board is drawn: which map artwork to
+//   use, the color of each power, what the units look like, whether the
+//   province names are shown and how big the output is
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    // path to the SVG artwork, with a group for each province whose id is
+    //   the province name
+    pub map_svg: String,
+    // colors are written like "#B19517"
+    pub palette: HashMap<Power, String>,
+    // used for any power that's missing from the palette
+    pub default_color: String,
+    // how much lighter units are drawn than the provinces their owner holds
+    pub unit_luminance: f32,
+    // SVG path data for the unit glyphs and the size of the box they fit in
+    pub army_glyph: String,
+    pub fleet_glyph: String,
+    pub glyph_size: (f32, f32),
+    // id of the group holding the province names in the artwork
+    pub labels_id: String,
+    pub show_labels: bool,
+    // width and height of the output; the artwork is scaled to fit
+    pub size: Option<(u32, u32)>,
+    // whether to draw a box with the phase and each power's standings, and
+    //   where its top left corner goes in artwork coordinates
+    pub show_legend: bool,
+    pub legend_position: (f32, f32),
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        let mut palette = HashMap::new();
+        palette.insert(Power::from("Austria"), String::from("#B19517"));
+        palette.insert(Power::from("Russia"), String::from("#613697"));
+        palette.insert(Power::from("Italy"), String::from("#4CB56A"));
+        palette.insert(Power::from("Germany"), String::from("#C66813"));
+        palette.insert(Power::from("France"), String::from("#0766B9"));
+        palette.insert(Power::from("England"), String::from("#9C1E1E"));
+        palette.insert(Power::from("Turkey"), String::from("#3487AD"));
+
+        RenderOptions {
+            map_svg: String::from("data/standard.svg"),
+            palette,
+            default_color: String::from("#808080"),
+            unit_luminance: 15.,
+            army_glyph: String::from("M3.50009 14L7.00009 18.5H35L36 17.5L39 14.5C41.8 11.7 39.5 8.5 37.5 8L27.5 7.5H23.5V6.5H26.5C27 6 28.8 5 32 5C35.2 5 33.3333 3 32 2H29L28 1H24L23 2H19.5L18.5 2.5H14L13 3.5H1V5H13L16 6.5H18.5V7.5H11.5C11 8.5 8.5 10 7.00009 10C2.20009 10 2.66676 12.6667 3.50009 14Z"),
+            fleet_glyph: String::from("M31 14.5V12M31 12V11H28.5V8H26.5L25 6.5V1H23V3L21 4.5V10V8H20.5V5H17V10V7H15V9.5H14V12H12V14H11.5V12H9V14H1V16.5L2.5 18H41.5L45 12H41V13H37V14H34V12M31 12H34M35.5 12H34"),
+            glyph_size: (42., 20.),
+            labels_id: String::from("Textes"),
+            show_labels: true,
+            size: None,
+            show_legend: false,
+            legend_position: (10., 10.),
+        }
+    }
+}
+
+impl RenderOptions {
+    // the color a power's provinces are filled with
+    pub fn color(&self, power: &Power) -> &str {
+        self.palette.get(power).unwrap_or(&self.default_color)
+    }
+
+    // the color a power's units are filled with
+    pub fn unit_color(&self, power: &Power) -> String {
+        adjust_luminance(self.color(power), self.unit_luminance)
+    }
+
+    // check that every color is one that can be lightened and darkened
+    pub fn check_colors(&self) -> Result<(), RenderError> {
+        let mut colors: Vec<(&str, &str)> = self
+            .palette
+            .iter()
+            .map(|(power, color)| (&power.name[..], &color[..]))
+            .collect();
+        colors.sort();
+        colors.push(("the default", &self.default_color));
+        match colors
+            .into_iter()
+            .find(|&(_, color)| hex_to_rgb(color).is_none())
+        {
+            Some((name, color)) => Err(RenderError::InvalidColor(
+                name.to_string(),
+                color.to_string(),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn glyph(&self, unit_type: UnitType) -> &str {
+        match unit_type {
+            UnitType::Army => &self.army_glyph,
+            UnitType::Fleet => &self.fleet_glyph,
+        }
+    }
+}
+
+// why an image couldn't be made
+#[derive(Clone, Debug, PartialEq)]
+pub enum RenderError {
+    // the artwork file couldn't be read
+    MissingArtwork(String, String),
+    // the artwork (or the map drawn from it) isn't a valid SVG document
+    InvalidArtwork(String),
+    // a power's color (or the default one) isn't written like "#B19517"
+    InvalidColor(String, String),
+    // GIF frames can't be wider or taller than 65535 pixels
+    TooLarge(u32, u32),
+    Encoding(String),
+    // the output file couldn't be written
+    Output(String),
+}
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RenderError::MissingArtwork(ref path, ref e) => {
+                write!(f, "couldn't read the map artwork {}: {}", path, e)
+            }
+            RenderError::InvalidArtwork(ref e) => write!(f, "invalid map artwork: {}", e),
+            RenderError::InvalidColor(ref name, ref color) => {
+                write!(f, "{} color {:?} isn't like #B19517", name, color)
+            }
+            RenderError::TooLarge(width, height) => {
+                write!(f, "a {}x{} image is too large", width, height)
+            }
+            RenderError::Encoding(ref e) => write!(f, "couldn't encode the image: {}", e),
+            RenderError::Output(ref e) => write!(f, "couldn't write the image: {}", e),
+        }
+    }
+}
+
 impl Stpsyr {
-    pub fn render(&self) -> String{
-        let file_path = "data/standard.svg";
-        let mut colors = HashMap::new();
-        let mut definitions = HashMap::new();
+    pub fn render(&self) -> Result<String, RenderError> {
+        self.render_with(&RenderOptions::default())
+    }
 
-        definitions.insert(Power::from("Austria"), "#B19517");
-        definitions.insert(Power::from("Russia"), "#613697");
-        definitions.insert(Power::from("Italy"), "#4CB56A");
-        definitions.insert(Power::from("Germany"), "#C66813");
-        definitions.insert(Power::from("France"), "#0766B9");
-        definitions.insert(Power::from("England"), "#9C1E1E");
-        definitions.insert(Power::from("Turkey"), "#3487AD");
+    pub fn render_with(&self, options: &RenderOptions) -> Result<String, RenderError> {
+        options.check_colors()?;
+        let mut colors = HashMap::new();
 
         // Read SVG file
-        let svg_content = fs::read_to_string(file_path).expect("Failed to read SVG file");
+        let svg_content = fs::read_to_string(&options.map_svg)
+            .map_err(|e| RenderError::MissingArtwork(options.map_svg.clone(), e.to_string()))?;
 
         // Step 1: Collect colors based on ownership
         for region in self.map.iter() {
             if let Some(ref owner) = region.owner {
-                println!("Region {:?} is owned by {:?}", region.province, owner);
+                trace!(
+                    phase:% = self.phase, year = self.year;
+                    "region {:?} is owned by {}",
+                    region.province,
+                    owner.name
+                );
                 colors.insert(
                     region.province.name.clone(),
-                    definitions.get(owner).unwrap().to_string(),
+                    options.color(owner).to_string(),
                 );
             }
         }
 
-        // Step 2: Modify SVG colors
-        let modified_svg = change_fill_colors(&svg_content, &colors);
-
+        // Step 2: Modify SVG colors, and resize the output and hide the
+        //   labels if asked to
+        let hidden = if options.show_labels {
+            vec![]
+        } else {
+            vec![&options.labels_id[..]]
+        };
+        let modified_svg = edit_svg(&svg_content, &colors, &hidden, options.size)?;
 
         // Step 4: Write modified SVG up to `</svg>`
         let svg_closing = "</svg>";
@@ -48,89 +188,607 @@ impl Stpsyr {
         for region in self.map.iter() {
             if let Some(ref unit) = region.unit {
                 let (x, y) = region.center;
-                let color = adjust_luminance(definitions.get(&unit.owner).unwrap(), 15.);
-
-                let unit_svg = match unit.unit_type {
-                    UnitType::Army => {
-                        format!(
-r#"<g style="fill:{}" stroke="black" stroke-width="2" transform="translate({},{})">
-    <path d="M3.50009 14L7.00009 18.5H35L36 17.5L39 14.5C41.8 11.7 39.5 8.5 37.5 8L27.5 7.5H23.5V6.5H26.5C27 6 28.8 5 32 5C35.2 5 33.3333 3 32 2H29L28 1H24L23 2H19.5L18.5 2.5H14L13 3.5H1V5H13L16 6.5H18.5V7.5H11.5C11 8.5 8.5 10 7.00009 10C2.20009 10 2.66676 12.6667 3.50009 14Z" stroke="black"/>
-</g>"#, color, x, y)
-                    }
-                    UnitType::Fleet => {
-                        format!(
-r#"<g style="fill:{}" stroke="black" stroke-width="2" transform="translate({},{})">
-    <path d="M31 14.5V12M31 12V11H28.5V8H26.5L25 6.5V1H23V3L21 4.5V10V8H20.5V5H17V10V7H15V9.5H14V12H12V14H11.5V12H9V14H1V16.5L2.5 18H41.5L45 12H41V13H37V14H34V12M31 12H34M35.5 12H34" stroke="black"/>
-</g>"#, color, x, y)
-                    }
-                };
-                content.push_str(&unit_svg);
+                content.push_str(&unit_svg(
+                    options.glyph(unit.unit_type),
+                    &options.unit_color(&unit.owner),
+                    "black",
+                    x,
+                    y,
+                ));
+            }
+        }
+
+        // Step 6: Draw the orders of the last adjudicated phase on top
+        if let Some(record) = self.history.last() {
+            content.push_str(&self.render_orders(&record.orders, options));
+        }
+
+        // Step 7: Show dislodged units next to their provinces, along with
+        //   where they can and can't retreat to, or the builds and disbands
+        //   ordered so far
+        match self.phase {
+            Phase::SpringRetreats | Phase::FallRetreats => {
+                content.push_str(&self.render_retreats(options));
+            }
+            Phase::Builds => {
+                content.push_str(&self.render_adjusts(options));
             }
+            _ => {}
+        }
+
+        // Step 8: Add the legend last so that nothing covers it
+        if options.show_legend {
+            content.push_str(&self.render_legend(options));
         }
 
         content.push_str(svg_closing);
         content.push('\n');
-        content
+        Ok(content)
     }
-    
-    pub fn render_to_file(&self, output_file: String) {
-        let modified_svg = self.render();
-        let mut out_file = File::create(output_file).expect("Failed to create output file");
-        writeln!(out_file, "{}", modified_svg).expect("Failed to write to file");
+
+    // a box titled with the phase and year, listing every power's color,
+    //   SC and unit counts, and during builds how many units it gains or
+    //   loses
+    fn render_legend(&self, options: &RenderOptions) -> String {
+        let sc_counts = self.sc_counts();
+        let unit_counts = self.unit_counts();
+        let deltas = self.adjust_deltas();
+        let builds = self.phase == Phase::Builds;
+
+        // powers without a home center (which can only turn up in edited
+        //   positions) go after the usual ones
+        let mut powers = self.powers();
+        let mut others: Vec<&Power> = sc_counts
+            .keys()
+            .chain(unit_counts.keys())
+            .filter(|p| !powers.contains(p))
+            .collect();
+        others.sort_by_key(|p| p.name.clone());
+        others.dedup();
+        powers.extend(others.into_iter().cloned());
+
+        let (x, y) = options.legend_position;
+        let width = if builds { 240. } else { 200. };
+        let height = 56. + 20. * powers.len() as f32;
+        let mut content = format!(
+            r##"<rect x="{}" y="{}" width="{}" height="{}" rx="4" fill="#1E1E1E" fill-opacity="0.85" stroke="#FFFFFF"/>
+<text x="{}" y="{}" font-size="16" font-weight="bold">{} {}</text>
+<text x="{}" y="{}" font-size="11" text-anchor="end">SCs</text><text x="{}" y="{}" font-size="11" text-anchor="end">Units</text>{}
+"##,
+            x,
+            y,
+            width,
+            height,
+            x + 10.,
+            y + 22.,
+            self.phase,
+            self.year,
+            x + 150.,
+            y + 40.,
+            x + 190.,
+            y + 40.,
+            if builds {
+                format!(
+                    r#"<text x="{}" y="{}" font-size="11" text-anchor="end">+/-</text>"#,
+                    x + 230.,
+                    y + 40.
+                )
+            } else {
+                String::new()
+            }
+        );
+
+        for (n, power) in powers.iter().enumerate() {
+            let row = y + 58. + 20. * n as f32;
+            let delta = deltas.get(power).cloned().unwrap_or(0);
+            content.push_str(&format!(
+                r##"<rect x="{}" y="{}" width="12" height="12" fill="{}" stroke="#FFFFFF"/><text x="{}" y="{}">{}</text><text x="{}" y="{}" text-anchor="end">{}</text><text x="{}" y="{}" text-anchor="end">{}</text>{}
+"##,
+                x + 10.,
+                row - 11.,
+                options.color(power),
+                x + 30.,
+                row,
+                escape_xml(&power.name),
+                x + 150.,
+                row,
+                sc_counts.get(power).unwrap_or(&0),
+                x + 190.,
+                row,
+                unit_counts.get(power).unwrap_or(&0),
+                if builds && delta != 0 {
+                    format!(
+                        r#"<text x="{}" y="{}" text-anchor="end">{:+}</text>"#,
+                        x + 230.,
+                        row,
+                        delta
+                    )
+                } else {
+                    String::new()
+                }
+            ));
+        }
+
+        format!(
+            r##"<g id="legend" font-family="sans-serif" font-size="14" fill="#FFFFFF">
+{}</g>
+"##,
+            content
+        )
     }
-}
 
-fn change_fill_colors(svg: &str, color_map: &HashMap<String, String>) -> String {
-    let mut result = String::new();
-    let mut inside_target_group: Option<String> = None;
+    // draw each dislodged unit offset from its province, ring the provinces
+    //   it may retreat to and cross out the provinces left empty by standoffs
+    fn render_retreats(&self, options: &RenderOptions) -> String {
+        let mut content = String::new();
 
-    for line in svg.lines() {
-        let mut modified_line = line.to_string();
+        for province in &self.contested {
+            if self.get_unit(province).is_some() {
+                continue;
+            }
+            let (x, y) = self.unit_center(options, province);
+            content.push_str(&format!(
+                r##"<path d="M{},{}L{},{}M{},{}L{},{}" stroke="#CC0000" stroke-width="4" stroke-linecap="round"/>
+"##,
+                x - 10.,
+                y - 10.,
+                x + 10.,
+                y + 10.,
+                x - 10.,
+                y + 10.,
+                x + 10.,
+                y - 10.
+            ));
+        }
 
-        // Check if the line contains an id from our map
-        for id in color_map.keys() {
-            if line.contains(&format!("id=\"{}\"", id)) {
-                inside_target_group = Some(id.clone());
-                break;
+        for (province, unit) in &self.dislodged {
+            let region = self.get_region(province).unwrap();
+            let (x, y) = self.unit_center(options, province);
+            let (x, y) = (x + DISLODGED_OFFSET as f32, y + DISLODGED_OFFSET as f32);
+
+            for order in self.legal_orders(province) {
+                if let LegalOrder::Retreat(RetreatAction::Move { ref to }) = order {
+                    let (tx, ty) = self.unit_center(options, to);
+                    let (ex, ey) = shorten((x, y), (tx, ty), 18.);
+                    content.push_str(&format!(
+                        r##"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="#FFFFFF" stroke-width="2" stroke-dasharray="4 4"/><circle cx="{}" cy="{}" r="18" stroke="#FFFFFF" stroke-width="3"/>
+"##,
+                        x, y, ex, ey, tx, ty
+                    ));
+                }
             }
+
+            content.push_str(&unit_svg(
+                options.glyph(unit.unit_type),
+                &options.unit_color(&unit.owner),
+                "#CC0000",
+                region.center.0 + DISLODGED_OFFSET,
+                region.center.1 + DISLODGED_OFFSET,
+            ));
         }
 
-        // If inside a group and we find a fill attribute, replace it
-        if let Some(ref id) = inside_target_group {
-            if modified_line.contains("fill=") {
-                if let Some(new_color) = color_map.get(id) {
-                    modified_line = replace_fill(&modified_line, new_color);
+        format!(
+            r#"<g id="retreats" fill="none">
+{}</g>
+"#,
+            content
+        )
+    }
+
+    // ring the units being built and cross out the units being disbanded
+    fn render_adjusts(&self, options: &RenderOptions) -> String {
+        let mut content = String::new();
+
+        for adjust in &self.adjusts {
+            let (x, y) = self.unit_center(options, &adjust.province);
+            match adjust.action {
+                AdjustAction::Build { unit_type, .. } => {
+                    let region = self.get_region(&adjust.province).unwrap();
+                    content.push_str(&unit_svg(
+                        options.glyph(unit_type),
+                        &options.unit_color(&adjust.owner),
+                        "black",
+                        region.center.0,
+                        region.center.1,
+                    ));
+                    content.push_str(&format!(
+                        r##"<circle cx="{}" cy="{}" r="26" stroke="#FFFFFF" stroke-width="3"/>
+"##,
+                        x, y
+                    ));
+                }
+                AdjustAction::Disband => {
+                    content.push_str(&format!(
+                        r##"<path d="M{},{}L{},{}M{},{}L{},{}" stroke="#CC0000" stroke-width="4" stroke-linecap="round"/>
+"##,
+                        x - 14.,
+                        y - 14.,
+                        x + 14.,
+                        y + 14.,
+                        x - 14.,
+                        y + 14.,
+                        x + 14.,
+                        y - 14.
+                    ));
                 }
             }
         }
 
-        result.push_str(&modified_line);
-        result.push('\n');
+        format!(
+            r#"<g id="adjusts" fill="none">
+{}</g>
+"#,
+            content
+        )
+    }
+
+    // draw move arrows, support lines, convoy paths and hold circles for a
+    //   set of adjudicated orders; orders that failed are drawn faded
+    fn render_orders(&self, orders: &[Order], options: &RenderOptions) -> String {
+        let mut content = String::new();
+        // arrowheads have to be colored separately for each power
+        let mut markers: Vec<String> = vec![];
+
+        for order in orders {
+            let color = adjust_luminance(options.color(&order.owner), -options.unit_luminance);
+            let opacity = if order.resolution { "1" } else { "0.4" };
+            let marker = format!(
+                "arrow{}{}",
+                color.trim_start_matches('#'),
+                if order.resolution { "" } else { "f" }
+            );
+            if !markers.contains(&marker) {
+                markers.push(marker.clone());
+                content.push_str(&format!(
+                    r#"<marker id="{}" viewBox="0 0 10 10" refX="8" refY="5" markerWidth="4" markerHeight="4" orient="auto"><path d="M0,0L10,5L0,10Z" fill="{}" fill-opacity="{}" stroke="none"/></marker>
+"#,
+                    marker, color, opacity
+                ));
+            }
+            let style = format!(r#"stroke="{}" stroke-opacity="{}""#, color, opacity);
+            let (x, y) = self.unit_center(options, &order.province);
+
+            let order_svg = match order.action {
+                Action::Hold => {
+                    format!(r#"<circle cx="{}" cy="{}" r="18" {}/>"#, x, y, style)
+                }
+                Action::Move { ref to, convoyed } => {
+                    let (tx, ty) = shorten((x, y), self.unit_center(options, to), 14.);
+                    format!(
+                        r#"<line x1="{}" y1="{}" x2="{}" y2="{}" {}{} marker-end="url(#{})"/>"#,
+                        x,
+                        y,
+                        tx,
+                        ty,
+                        style,
+                        if convoyed {
+                            r#" stroke-dasharray="1 6""#
+                        } else {
+                            ""
+                        },
+                        marker
+                    )
+                }
+                Action::SupportHold { ref to } => {
+                    let (tx, ty) = self.unit_center(options, to);
+                    let (ex, ey) = shorten((x, y), (tx, ty), 18.);
+                    format!(
+                        r#"<line x1="{}" y1="{}" x2="{}" y2="{}" {} stroke-dasharray="6 4"/><circle cx="{}" cy="{}" r="18" {} stroke-dasharray="6 4"/>"#,
+                        x, y, ex, ey, style, tx, ty, style
+                    )
+                }
+                Action::SupportMove { ref from, ref to } => {
+                    // the line ends in the middle of the supported move
+                    let (fx, fy) = self.unit_center(options, from);
+                    let (tx, ty) = self.unit_center(options, to);
+                    format!(
+                        r#"<line x1="{}" y1="{}" x2="{}" y2="{}" {} stroke-dasharray="6 4" marker-end="url(#{})"/>"#,
+                        x,
+                        y,
+                        (fx + tx) / 2.,
+                        (fy + ty) / 2.,
+                        style,
+                        marker
+                    )
+                }
+                Action::Convoy { ref from, ref to } => {
+                    // the path runs from the army through the fleet to its
+                    //   destination
+                    let (fx, fy) = self.unit_center(options, from);
+                    let (tx, ty) = shorten((x, y), self.unit_center(options, to), 14.);
+                    format!(
+                        r#"<polyline points="{},{} {},{} {},{}" {} stroke-dasharray="1 6" marker-end="url(#{})"/>"#,
+                        fx, fy, x, y, tx, ty, style, marker
+                    )
+                }
+            };
+            content.push_str(&order_svg);
+            content.push('\n');
+        }
+
+        format!(
+            r#"<g id="orders" fill="none" stroke-width="3" stroke-linecap="round">
+{}</g>
+"#,
+            content
+        )
+    }
+
+    // the middle of a unit drawn in a province
+    fn unit_center(&self, options: &RenderOptions, province: &Province) -> (f32, f32) {
+        self.get_region(province).map_or((0., 0.), |r| {
+            (
+                r.center.0 as f32 + options.glyph_size.0 / 2.,
+                r.center.1 as f32 + options.glyph_size.1 / 2.,
+            )
+        })
+    }
+
+    pub fn render_to_file(&self, output_file: String) -> Result<(), RenderError> {
+        self.render_to_file_with(output_file, &RenderOptions::default())
+    }
+
+    pub fn render_to_file_with(
+        &self,
+        output_file: String,
+        options: &RenderOptions,
+    ) -> Result<(), RenderError> {
+        let modified_svg = self.render_with(options)?;
+        write_file(&output_file, format!("{}\n", modified_svg).as_bytes())
+    }
+
+    pub fn render_png(&self) -> Result<Vec<u8>, RenderError> {
+        self.render_png_with(&RenderOptions::default())
+    }
+
+    // rasterize the rendered map; the image is as big as options.size, or
+    //   the artwork itself if no size is given
+    pub fn render_png_with(&self, options: &RenderOptions) -> Result<Vec<u8>, RenderError> {
+        rasterize(&parse_svg(&self.render_with(options)?, &raster_options())?)?
+            .encode_png()
+            .map_err(|e| RenderError::Encoding(e.to_string()))
+    }
+
+    pub fn render_png_to_file(&self, output_file: String) -> Result<(), RenderError> {
+        self.render_png_to_file_with(output_file, &RenderOptions::default())
+    }
+
+    pub fn render_png_to_file_with(
+        &self,
+        output_file: String,
+        options: &RenderOptions,
+    ) -> Result<(), RenderError> {
+        let png = self.render_png_with(options)?;
+        write_file(&output_file, &png)
+    }
+}
 
-        // Stop modifying when we exit the group
-        if inside_target_group.is_some() && line.contains("</g>") {
-            inside_target_group = None;
+// render a sequence of positions, e.g. one for every phase of a game, as
+//   an animated GIF showing each position for delay hundredths of a second;
+//   every frame shows the orders that led to it, so units can be followed
+//   from one phase to the next, builds positions show the builds and
+//   disbands ordered in them, and the last frame is held three times as
+//   long before the replay loops
+pub fn render_replay(
+    positions: &[Stpsyr],
+    options: &RenderOptions,
+    delay: u16,
+) -> Result<Vec<u8>, RenderError> {
+    let mut out = vec![];
+    let usvg_options = raster_options();
+    let mut frames = positions
+        .iter()
+        .map(|position| parse_svg(&position.render_with(options)?, &usvg_options));
+    if let Some(first) = frames.next() {
+        let first = first?;
+        // every frame is the same size as the first, which is checked before
+        //   anything is drawn
+        let size = first.size().to_int_size();
+        let (width, height) = match (u16::try_from(size.width()), u16::try_from(size.height())) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => return Err(RenderError::TooLarge(size.width(), size.height())),
+        };
+        let encoding = |e: gif::EncodingError| RenderError::Encoding(e.to_string());
+        let mut encoder = gif::Encoder::new(&mut out, width, height, &[]).map_err(encoding)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(encoding)?;
+
+        for (n, tree) in std::iter::once(Ok(first)).chain(frames).enumerate() {
+            let mut rgba: Vec<u8> = rasterize(&tree?)?
+                .pixels()
+                .iter()
+                .flat_map(|p| {
+                    let c = p.demultiply();
+                    vec![c.red(), c.green(), c.blue(), c.alpha()]
+                })
+                .collect();
+            let mut frame = gif::Frame::from_rgba_speed(width, height, &mut rgba, 10);
+            frame.delay = if n + 1 == positions.len() {
+                delay.saturating_mul(3)
+            } else {
+                delay
+            };
+            encoder.write_frame(&frame).map_err(encoding)?;
         }
     }
+    Ok(out)
+}
 
-    result
+pub fn render_replay_to_file(
+    positions: &[Stpsyr],
+    output_file: String,
+    options: &RenderOptions,
+    delay: u16,
+) -> Result<(), RenderError> {
+    let gif = render_replay(positions, options, delay)?;
+    write_file(&output_file, &gif)
 }
 
-fn replace_fill(line: &str, new_color: &str) -> String {
-    let fill_pattern = r#"fill="([^"]*)""#; // Match `fill="..."`
+fn write_file(output_file: &str, contents: &[u8]) -> Result<(), RenderError> {
+    File::create(output_file)
+        .and_then(|mut out_file| out_file.write_all(contents))
+        .map_err(|e| RenderError::Output(format!("{}: {}", output_file, e)))
+}
 
-    let replaced_line = regex::Regex::new(fill_pattern)
-        .unwrap()
-        .replace(line, format!(r#"fill="{}""#, new_color))
-        .to_string();
+// the options for drawing SVG documents, with the system fonts loaded;
+//   loading them is slow, so this is done once per image or replay
+fn raster_options() -> resvg::usvg::Options<'static> {
+    let mut usvg_options = resvg::usvg::Options::default();
+    {
+        // the generic families default to fonts that may well not be
+        //   installed, so fall back to whatever is
+        let fontdb = usvg_options.fontdb_mut();
+        fontdb.load_system_fonts();
+        let query = resvg::usvg::fontdb::Query {
+            families: &[resvg::usvg::fontdb::Family::SansSerif],
+            ..Default::default()
+        };
+        if fontdb.query(&query).is_none() {
+            let family = fontdb
+                .faces()
+                .find(|face| !face.monospaced)
+                .or_else(|| fontdb.faces().next())
+                .and_then(|face| face.families.first())
+                .map(|family| family.0.clone());
+            if let Some(family) = family {
+                fontdb.set_sans_serif_family(family);
+            }
+        }
+    }
+    usvg_options
+}
 
-    replaced_line
+fn parse_svg(
+    svg: &str,
+    usvg_options: &resvg::usvg::Options,
+) -> Result<resvg::usvg::Tree, RenderError> {
+    resvg::usvg::Tree::from_str(svg, usvg_options)
+        .map_err(|e| RenderError::InvalidArtwork(e.to_string()))
 }
-fn hex_to_rgb(hex: &str) -> (u8, u8, u8) {
-    let r = u8::from_str_radix(&hex[1..3], 16).unwrap();
-    let g = u8::from_str_radix(&hex[3..5], 16).unwrap();
-    let b = u8::from_str_radix(&hex[5..7], 16).unwrap();
-    (r, g, b)
+
+// draw an SVG document onto an image as big as the document
+fn rasterize(tree: &resvg::usvg::Tree) -> Result<resvg::tiny_skia::Pixmap, RenderError> {
+    let size = tree.size().to_int_size();
+    let mut pixmap = resvg::tiny_skia::Pixmap::new(size.width(), size.height())
+        .ok_or(RenderError::TooLarge(size.width(), size.height()))?;
+    resvg::render(
+        tree,
+        resvg::tiny_skia::Transform::default(),
+        &mut pixmap.as_mut(),
+    );
+    Ok(pixmap)
+}
+
+// dislodged units are drawn this far right of and below the usual spot
+const DISLODGED_OFFSET: usize = 14;
+
+fn unit_svg(glyph: &str, color: &str, stroke: &str, x: usize, y: usize) -> String {
+    format!(
+        r#"<g style="fill:{}" stroke="{}" stroke-width="2" transform="translate({},{})">
+    <path d="{}" stroke="{}"/>
+</g>"#,
+        color, stroke, x, y, glyph, stroke
+    )
+}
+
+// move the end of a line towards its start so arrows don't cover units
+fn shorten(from: (f32, f32), to: (f32, f32), by: f32) -> (f32, f32) {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length <= by {
+        return to;
+    }
+    (to.0 - dx / length * by, to.1 - dy / length * by)
+}
+
+// parse the artwork and make every change render() needs in one go: fill
+//   everything under an element whose id is in color_map, hide the elements
+//   with the given ids and set the size of the root element (leaving the
+//   viewBox alone so the artwork scales)
+// only the affected attributes are rewritten, so the rest of the file comes
+//   out exactly as it went in
+fn edit_svg(
+    svg: &str,
+    color_map: &HashMap<String, String>,
+    hidden: &[&str],
+    size: Option<(u32, u32)>,
+) -> Result<String, RenderError> {
+    let doc =
+        roxmltree::Document::parse(svg).map_err(|e| RenderError::InvalidArtwork(e.to_string()))?;
+    let style_fill = regex::Regex::new(r"fill:\s*[^;]*").unwrap();
+
+    // edits are keyed by where they start and which attribute they set, so
+    //   when ids are nested the innermost one wins
+    let mut edits: BTreeMap<(usize, String), (Range<usize>, String)> = BTreeMap::new();
+    let mut set_attribute = |node: roxmltree::Node, name: &str, value: String| {
+        let value = escape_xml(&value);
+        if let Some(attr) = node.attributes().find(|a| a.name() == name) {
+            let range = attr.range_value();
+            edits.insert((range.start, name.to_string()), (range, value));
+        } else {
+            let at = node.range().start
+                + svg[node.range()]
+                    .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+                    .unwrap();
+            edits.insert(
+                (at, name.to_string()),
+                (at..at, format!(r#" {}="{}""#, name, value)),
+            );
+        }
+    };
+
+    for node in doc.descendants().filter(|n| n.is_element()) {
+        let id = if let Some(id) = node.attribute("id") {
+            id
+        } else {
+            continue;
+        };
+        if let Some(color) = color_map.get(id) {
+            for child in node.descendants().filter(|n| n.is_element()) {
+                if child.attribute("fill").is_some_and(|f| f != "none") {
+                    set_attribute(child, "fill", color.clone());
+                }
+                if let Some(style) = child.attribute("style").filter(|s| s.contains("fill:")) {
+                    let style = style_fill
+                        .replace_all(style, format!("fill:{}", color).as_str())
+                        .to_string();
+                    set_attribute(child, "style", style);
+                }
+            }
+        }
+        if hidden.contains(&id) {
+            set_attribute(node, "display", String::from("none"));
+        }
+    }
+
+    if let Some((width, height)) = size {
+        set_attribute(doc.root_element(), "width", width.to_string());
+        set_attribute(doc.root_element(), "height", height.to_string());
+    }
+
+    let mut result = svg.to_string();
+    for (_, (range, value)) in edits.into_iter().rev() {
+        result.replace_range(range, &value);
+    }
+    Ok(result)
+}
+
+// make text safe to put in an attribute value or between tags
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('"', "&quot;")
+}
+
+// e.g. (177, 149, 23) for "#B19517", or None if it isn't written like that
+fn hex_to_rgb(hex: &str) -> Option<(u8, u8, u8)> {
+    let hex = hex.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
 }
 
 fn rgb_to_hex(r: u8, g: u8, b: u8) -> String {
@@ -213,7 +871,11 @@ fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (u8, u8, u8) {
 
 // Adjust luminance by +10
 fn adjust_luminance(hex: &str, delta_l: f32) -> String {
-    let (r, g, b) = hex_to_rgb(hex);
+    let (r, g, b) = match hex_to_rgb(hex) {
+        Some(rgb) => rgb,
+        // render_with won't get this far; anything else gets it unchanged
+        None => return hex.to_string(),
+    };
     let (h, s, mut l) = rgb_to_hsl(r, g, b);
 
     l = (l + delta_l / 100.0).clamp(0.0, 1.0); // Ensure it's within range [0,1]
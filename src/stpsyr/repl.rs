@@ -0,0 +1,138 @@
+use std::io::{BufRead, Write};
+
+use stpsyr::types::*;
+
+const HELP: &str = "commands:
+  power <name>   enter orders for this power from now on
+  orders         list the orders entered so far this phase
+  clear          take back all of the current power's orders
+  adjudicate     resolve the current phase and move on to the next one
+  show           summarize the position
+  undo           take back the last order or adjudication
+  quit           stop (end of input works too)
+anything else is read as an order for the current power, replacing any
+earlier order for the same unit";
+
+// a Repl lets a game master run a game by typing commands and orders, e.g.
+//   for a face-to-face game with one laptop as the judge
+pub struct Repl {
+    pub game: Stpsyr,
+    // the power orders are entered for
+    pub power: Option<Power>,
+    // every position before an accepted order, a clear or an adjudication,
+    //   so that they can be taken back
+    undo: Vec<Stpsyr>,
+}
+
+impl Repl {
+    pub fn new(game: Stpsyr) -> Repl {
+        Repl {
+            game,
+            power: None,
+            undo: vec![],
+        }
+    }
+
+    // read commands until "quit" or the end of input, writing a prompt before
+    //   each one and the response after it
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, mut output: W) {
+        let mut lines = input.lines();
+        loop {
+            let prompt = self.power.as_ref().map_or("", |p| &p.name[..]);
+            write!(output, "{}> ", prompt).expect("Failed to write prompt");
+            output.flush().expect("Failed to write prompt");
+
+            let line = match lines.next() {
+                Some(Ok(line)) => line,
+                _ => break,
+            };
+            let command = line.trim();
+            if command == "quit" || command == "exit" {
+                break;
+            }
+            writeln!(output, "{}", self.execute(command)).expect("Failed to write response");
+        }
+    }
+
+    // carry out a single command or order and describe the result
+    pub fn execute(&mut self, line: &str) -> String {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            None => String::new(),
+            Some("help") => HELP.to_string(),
+            Some("power") => {
+                let name = words.collect::<Vec<&str>>().join(" ");
+                match self
+                    .game
+                    .powers()
+                    .into_iter()
+                    .find(|p| p.name.eq_ignore_ascii_case(&name))
+                {
+                    Some(power) => {
+                        let response = format!("entering orders for {}", power.name);
+                        self.power = Some(power);
+                        response
+                    }
+                    None => format!("no such power: {}", name),
+                }
+            }
+            Some("orders") => self.pending(),
+            Some("clear") => match self.power {
+                Some(ref power) => {
+                    self.undo.push(self.game.fork());
+                    self.game.clear_orders(power);
+                    format!("cleared the orders for {}", power.name)
+                }
+                None => String::from("no power selected; use power <name>"),
+            },
+            Some("adjudicate") => {
+                self.undo.push(self.game.fork());
+                self.game.apply();
+                format!("{} {}", self.game.phase, self.game.year)
+            }
+            Some("show") => self.game.summary().trim_end().to_string(),
+            Some("undo") => match self.undo.pop() {
+                Some(game) => {
+                    self.game.restore(game);
+                    format!("undone; {} {}", self.game.phase, self.game.year)
+                }
+                None => String::from("nothing to undo"),
+            },
+            Some(_) => {
+                let power = match self.power {
+                    Some(ref power) => power.clone(),
+                    None => return String::from("no power selected; use power <name>"),
+                };
+                let before = self.game.fork();
+                if self.game.parse(&power, line.to_string()) > 0 {
+                    self.undo.push(before);
+                    format!("accepted: {}", line)
+                } else {
+                    format!("rejected: {}", line)
+                }
+            }
+        }
+    }
+
+    // the orders entered so far in the current phase, by power
+    fn pending(&self) -> String {
+        let mut powers = self.game.powers();
+        powers.sort_by_key(|p| p.name.clone());
+        let listing: Vec<String> = powers
+            .iter()
+            .filter_map(|power| {
+                let orders = self.game.pending_orders(power);
+                if orders.is_empty() {
+                    None
+                } else {
+                    Some(format!("{}\n  {}", power.name, orders.join("\n  ")))
+                }
+            })
+            .collect();
+        if listing.is_empty() {
+            String::from("no orders yet")
+        } else {
+            listing.join("\n")
+        }
+    }
+}
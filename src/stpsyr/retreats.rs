@@ -1,43 +1,80 @@
 use std::collections::HashSet;
 
+use stpsyr::observer::EngineEvent;
 use stpsyr::types::*;
 
 impl Stpsyr {
-    // the publicly exposed function to modify self.retreats
-    pub fn add_retreat(&mut self, owner: Power, province: Province, action: RetreatAction) {
+    // the publicly exposed function to modify self.retreats; returns whether
+    //   the retreat was accepted, replacing any earlier one for the unit
+    pub fn add_retreat(&mut self, owner: Power, province: Province, action: RetreatAction) -> bool {
         // TODO refactor this method to get rid of repetition from verification
         //   used in add_order
-        // TODO can't retreat to the place that attacked you
 
         match self.phase {
             Phase::SpringRetreats | Phase::FallRetreats => {}
             _ => panic!("add_retreat called during non-retreat phase"),
         };
 
+        if !self.retreat_is_legal(&owner, &province, &action) {
+            return false;
+        }
+
+        let retreat = Retreat {
+            owner,
+            province,
+            action,
+        };
+        if let Some(existing) = self
+            .retreats
+            .iter_mut()
+            .find(|r| r.province == retreat.province)
+        {
+            *existing = retreat;
+        } else {
+            self.retreats.push(retreat);
+        }
+        true
+    }
+
+    // the checks behind add_retreat, also used to generate legal orders
+    pub fn retreat_is_legal(
+        &self,
+        owner: &Power,
+        province: &Province,
+        action: &RetreatAction,
+    ) -> bool {
         // there has to be a unit that was dislodged here to order it
         let unit = if let Some(unit) = self
             .dislodged
             .iter()
-            .find(|&(p, _)| p == &province)
+            .find(|&(p, _)| p == province)
             .map(|(_, u)| u.clone())
         {
             unit
         } else {
-            return;
+            return false;
         };
+        let attacker = self
+            .attacked_from
+            .iter()
+            .find(|&(p, _)| p == province)
+            .map(|(_, a)| a);
 
         // can't order a unit that's not yours
-        if unit.owner != owner {
-            return;
+        if unit.owner != *owner {
+            return false;
         }
 
-        // can't order to a province you can't reach or a province that was
-        //   contested during the last diplomacy phase
-        if match &action {
+        // can't order to a province you can't reach, a province that was
+        //   contested during the last diplomacy phase, one that's occupied, or
+        //   the one the attacker came from (unless it was convoyed)
+        match action {
             RetreatAction::Move { to } => {
-                let r = self.get_region(&province).unwrap();
-                self.contested.contains(to)
-                    || !match unit.unit_type {
+                let r = self.get_region(province).unwrap();
+                !self.contested.contains(to)
+                    && attacker.is_none_or(|a| a.name != to.name)
+                    && self.get_unit(to).is_none()
+                    && match unit.unit_type {
                         UnitType::Army => r.army_borders.clone(),
                         UnitType::Fleet => r
                             .fleet_borders
@@ -48,31 +85,26 @@ impl Stpsyr {
                     }
                     .contains(to)
             }
-            _ => false,
-        } {
-            return;
+            _ => true,
         }
-
-        self.retreats.push(Retreat {
-            owner,
-            province,
-            action,
-        });
     }
 
     // the publicly exposed function that is called once all retreats have been
     //   added
-    // TODO this breaks if multiple retreat orders are submitted for a single
-    //   province. and yes, that should definitely be handled in add_retreat,
-    //   but I'm lazy and don't wanna scroll all the way back up there to add
-    //   the comment in the right place
+    // (add_retreat makes sure there's at most one retreat per province)
     pub fn apply_retreats(&mut self) {
+        self.record_nmrs();
         {
             // we need a new scope for these to release the borrows later
             let (mut attempts, mut conflicts) = (HashSet::new(), HashSet::new());
+            let mut retreated = vec![];
 
             for retreat in self.retreats.iter() {
-                println!("{:?}", retreat.owner);
+                debug!(
+                    phase:% = self.phase, year = self.year, power = &retreat.owner.name[..];
+                    "retreat {}",
+                    retreat
+                );
                 if let RetreatAction::Move { to } = &retreat.action {
                     if attempts.contains(to) {
                         conflicts.insert(to);
@@ -97,16 +129,33 @@ impl Stpsyr {
                             let (_, unit) = self.dislodged.get(from_idx).unwrap();
 
                             self.map[to_idx].unit = Some(unit.clone());
+                            retreated.push(&retreat.province);
+                            self.notify(EngineEvent::UnitRetreated {
+                                from: retreat.province.clone(),
+                                to: to.clone(),
+                                unit: unit.clone(),
+                            });
                         }
                     }
                     // handle disbands as if they were NMRs - no difference anyway
                     &RetreatAction::Disband => {}
                 }
             }
+
+            // every other dislodged unit is gone
+            for (province, unit) in &self.dislodged {
+                if !retreated.contains(&province) {
+                    self.notify(EngineEvent::UnitDisbanded {
+                        province: province.clone(),
+                        unit: unit.clone(),
+                    });
+                }
+            }
         }
 
         self.next_phase();
         self.dislodged = vec![];
+        self.attacked_from = vec![];
         self.retreats = vec![];
     }
 }
@@ -0,0 +1,869 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::mpsc::{self, SyncSender, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+use stpsyr::deadlines::{Clock, SystemClock};
+use stpsyr::events::Event;
+use stpsyr::rendering::RenderOptions;
+use stpsyr::storage::Storage;
+use stpsyr::types::*;
+use tungstenite::handshake::server::{ErrorResponse, Request};
+use tungstenite::protocol::Role;
+use tungstenite::WebSocket;
+
+// a game being run by the server, along with the name of its map so that
+//   the right artwork can be found, and the secret tokens that let each
+//   power (and the game master) act in it
+pub struct Game {
+    pub map: String,
+    pub state: Stpsyr,
+    pub tokens: HashMap<Power, String>,
+    pub master_token: String,
+}
+
+// everything about a Game other than its position, as it's kept in storage
+#[derive(Serialize, Deserialize)]
+struct GameMetadata {
+    map: String,
+    tokens: BTreeMap<String, String>,
+    master_token: String,
+}
+
+// who a request was made by, going by its token
+#[derive(Debug, PartialEq)]
+enum Access {
+    Master,
+    Power(Power),
+    Public,
+}
+
+impl Game {
+    // find the power with the given name, checking that whoever made the
+    //   request may act for it
+    fn authorize(
+        &self,
+        access: &Access,
+        token: Option<&str>,
+        name: &str,
+    ) -> Result<Power, Response> {
+        let power = match self
+            .state
+            .powers()
+            .into_iter()
+            .find(|p| p.name.eq_ignore_ascii_case(name))
+        {
+            Some(power) => power,
+            None => return Err(Response::error(404, "no such power")),
+        };
+        match *access {
+            Access::Master => Ok(power),
+            Access::Power(ref p) if *p == power => Ok(power),
+            Access::Public if token.is_none() => Err(Response::error(401, "a token is needed")),
+            _ => Err(Response::error(403, "that token is for someone else")),
+        }
+    }
+
+    // the game as whoever has the given access may see it, without anyone
+    //   else's pending orders
+    fn view(&self, access: &Access) -> Cow<'_, Stpsyr> {
+        match *access {
+            Access::Master => Cow::Borrowed(&self.state),
+            Access::Power(ref power) => Cow::Owned(self.state.redacted(Some(power))),
+            Access::Public => Cow::Owned(self.state.redacted(None)),
+        }
+    }
+
+    fn access(&self, token: Option<&str>) -> Access {
+        match token {
+            Some(token) if token == self.master_token => Access::Master,
+            Some(token) => self
+                .tokens
+                .iter()
+                .find(|&(_, t)| t == token)
+                .map_or(Access::Public, |(p, _)| Access::Power(p.clone())),
+            None => Access::Public,
+        }
+    }
+}
+
+// how many events can wait for a subscriber before it's dropped as too slow
+const SUBSCRIBER_BACKLOG: usize = 64;
+// how long a subscriber's connection can go without taking a write before
+//   it's given up on
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+// how long a subscriber waits for frames from the client before checking
+//   for events again
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+// how long a client gets to finish the WebSocket handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+// how often serve looks for new WebSocket connections and passed deadlines
+//   when there are no requests
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// anything a WebSocket can run over; reads and writes have to be able to
+//   time out, so that a subscriber can wait for frames from the client and
+//   events from the server at the same time
+pub trait Socket: Read + Write + Send {
+    fn set_timeouts(&self, read: Duration, write: Duration) -> io::Result<()>;
+}
+
+impl Socket for TcpStream {
+    fn set_timeouts(&self, read: Duration, write: Duration) -> io::Result<()> {
+        self.set_read_timeout(Some(read))?;
+        self.set_write_timeout(Some(write))
+    }
+}
+
+// a WebSocket connection that a game's events are sent to, along with who
+//   opened it so that each event can be redacted for them; the connection
+//   is written on its own thread so that a slow client can't hold up the
+//   server
+struct Subscriber {
+    game: u64,
+    access: Access,
+    events: SyncSender<String>,
+}
+
+// send events to a subscriber until it goes away or the server drops it,
+//   reading frames from the client in between so that pings are answered
+//   and closes are noticed
+fn run_subscriber<S: Socket>(mut socket: WebSocket<S>, events: mpsc::Receiver<String>) {
+    if socket
+        .get_ref()
+        .set_timeouts(READ_TIMEOUT, WRITE_TIMEOUT)
+        .is_err()
+    {
+        return;
+    }
+    loop {
+        match events.try_recv() {
+            Ok(json) => {
+                if socket.send(tungstenite::Message::text(json)).is_err() {
+                    return;
+                }
+                continue;
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => {
+                let _ = socket.close(None);
+                let _ = socket.flush();
+                return;
+            }
+        }
+        // pongs and close replies are queued by read and sent by flush
+        match socket.read().and_then(|_| socket.flush()) {
+            Ok(()) => {}
+            Err(tungstenite::Error::Io(ref err))
+                if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut => {}
+            Err(_) => return,
+        }
+    }
+}
+
+// what the server sends back for a request
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: u16, value: serde_json::Value) -> Response {
+        Response {
+            status,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response::json(status, serde_json::json!({ "error": message }))
+    }
+}
+
+// a Server keeps any number of games in memory and answers requests about
+//   them:
+//
+//   POST /games                         create a game; the body is JSON like
+//                                       {"map": "standard"}, naming a map in
+//                                       map_dir (the default is "standard"),
+//                                       optionally with "phase_lengths" like
+//                                       {"diplomacy": 86400, "retreats": 3600,
+//                                       "builds": 3600} in seconds, and
+//                                       "press" like {"private": true,
+//                                       "broadcast": true, "gray": false} (or
+//                                       "gunboat": true for none at all); the
+//                                       response has the tokens
+//   GET  /games                         list the games
+//   GET  /games/<id>                    the position as JSON, without the
+//                                       pending orders of other powers
+//   POST /games/<id>/orders/<power>     orders for a power, one per line,
+//                                       each replacing any earlier order for
+//                                       the same unit
+//   GET  /games/<id>/orders/<power>     a power's orders so far
+//   DELETE /games/<id>/orders/<power>   take back all of a power's orders
+//   POST /games/<id>/ready/<power>      mark a power as done ordering, or
+//                                       not done with a body of false
+//   POST /games/<id>/press/<power>      send a message as a power; the body
+//                                       is JSON like {"to": ["France"],
+//                                       "body": "hi", "anonymous": false},
+//                                       with no recipients for a broadcast
+//                                       (these five need the power's token)
+//   GET  /games/<id>/press              the messages the token's power can
+//                                       read
+//   POST /games/<id>/adjudicate         resolve the current phase (needs
+//                                       the master token)
+//   GET  /games/<id>/results            the orders of the last diplomacy
+//                                       phase and whether each succeeded
+//   GET  /games/<id>/map.svg            the rendered map
+//   GET  /games/<id>/events             a WebSocket that every event in the
+//                                       game is pushed to as JSON (see
+//                                       events::Event), redacted for the
+//                                       token's power; served on the events
+//                                       address rather than with the rest
+//
+// tokens are sent in an "Authorization: Bearer <token>" header, or as a
+//   ?token=<token> query since browsers can't set headers on WebSockets;
+//   the game master's token works for every power
+//
+// a phase is adjudicated by itself once every power is ready or its
+//   deadline passes
+pub struct Server {
+    // maps are read from <map_dir>/<name>.csv and drawn with
+    //   <map_dir>/<name>.svg
+    pub map_dir: String,
+    pub games: BTreeMap<u64, Game>,
+    next_id: u64,
+    clock: Box<dyn Clock>,
+    // games are written here whenever they change, if it's set
+    storage: Option<Storage>,
+    // events that haven't been sent to subscribers yet, by game
+    events: Vec<(u64, Event)>,
+    subscribers: Vec<Subscriber>,
+}
+
+impl Server {
+    pub fn new(map_dir: &str) -> Server {
+        Server::with_clock(map_dir, Box::new(SystemClock))
+    }
+
+    // a server that goes by the given clock instead of the actual time
+    pub fn with_clock(map_dir: &str, clock: Box<dyn Clock>) -> Server {
+        Server {
+            map_dir: map_dir.to_string(),
+            games: BTreeMap::new(),
+            next_id: 1,
+            clock,
+            storage: None,
+            events: vec![],
+            subscribers: vec![],
+        }
+    }
+
+    // pick up every game kept in storage and keep it up to date from now on,
+    //   so the server can be restarted without losing anything
+    pub fn attach_storage(&mut self, storage: Storage) -> Result<(), String> {
+        for id in storage.ids().map_err(|err| err.to_string())? {
+            let state = storage.load(id).map_err(|err| err.to_string())?;
+            let metadata = storage.metadata(id).map_err(|err| err.to_string())?;
+            let (state, metadata) = match (state, metadata) {
+                (Some(state), Some(metadata)) => (state, metadata),
+                _ => continue,
+            };
+            let metadata: GameMetadata =
+                serde_json::from_str(&metadata).map_err(|err| err.to_string())?;
+            self.games.insert(
+                id,
+                Game {
+                    map: metadata.map,
+                    state,
+                    tokens: metadata
+                        .tokens
+                        .into_iter()
+                        .map(|(p, t)| (Power::from(p), t))
+                        .collect(),
+                    master_token: metadata.master_token,
+                },
+            );
+            self.next_id = self.next_id.max(id + 1);
+        }
+        self.storage = Some(storage);
+        Ok(())
+    }
+
+    // write a game to storage, if there is any
+    fn persist(&mut self, id: u64) -> Result<(), String> {
+        let (storage, game) = match (self.storage.as_mut(), self.games.get(&id)) {
+            (Some(storage), Some(game)) => (storage, game),
+            _ => return Ok(()),
+        };
+        let metadata = GameMetadata {
+            map: game.map.clone(),
+            tokens: game
+                .tokens
+                .iter()
+                .map(|(p, t)| (p.name.clone(), t.clone()))
+                .collect(),
+            master_token: game.master_token.clone(),
+        };
+        storage
+            .save(id, &game.state)
+            .and_then(|_| storage.set_metadata(id, &serde_json::to_string(&metadata).unwrap()))
+            .map_err(|err| err.to_string())
+    }
+
+    // adjudicate every game whose phase is over
+    pub fn tick(&mut self) {
+        let mut adjudicated = vec![];
+        let clock = &*self.clock;
+        for (&id, game) in self.games.iter_mut() {
+            if !game.state.is_due(clock) {
+                continue;
+            }
+            // if adjudicating panics, the game is put back the way it was and
+            //   nobody is ready any more, with a fresh deadline, so that it
+            //   isn't tried again every second
+            let backup = game.state.clone();
+            match panic::catch_unwind(AssertUnwindSafe(|| game.state.tick(clock))) {
+                Ok(true) => {
+                    for event in game.state.events_after(&backup) {
+                        self.events.push((id, event));
+                    }
+                    adjudicated.push(id);
+                }
+                Ok(false) => {}
+                Err(_) => {
+                    error!(game = id; "adjudication panicked");
+                    game.state.restore(backup);
+                    game.state.ready.clear();
+                    game.state.deadline = None;
+                }
+            }
+        }
+        for id in adjudicated {
+            if let Err(err) = self.persist(id) {
+                error!(game = id; "failed to save the game: {}", err);
+            }
+        }
+        self.publish();
+    }
+
+    // start sending a game's events to a client, over a connection that has
+    //   already been through the WebSocket handshake; url is the
+    //   /games/<id>/events it asked for
+    pub fn subscribe<S: Socket + 'static>(
+        &mut self,
+        url: &str,
+        token: Option<&str>,
+        stream: S,
+    ) -> Result<(), Response> {
+        let (game, access) = self.subscription(url, token)?;
+        let socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+        self.add_subscriber(game, access, socket);
+        Ok(())
+    }
+
+    fn add_subscriber<S: Socket + 'static>(
+        &mut self,
+        game: u64,
+        access: Access,
+        socket: WebSocket<S>,
+    ) {
+        let (events, receiver) = mpsc::sync_channel(SUBSCRIBER_BACKLOG);
+        thread::spawn(move || run_subscriber(socket, receiver));
+        self.subscribers.push(Subscriber {
+            game,
+            access,
+            events,
+        });
+    }
+
+    // do the WebSocket handshake on a new connection to the events address,
+    //   then start sending it events, or turn it away if there's nothing to
+    //   subscribe to
+    // (the handshake callback's error type is tungstenite's, large or not)
+    #[allow(clippy::result_large_err)]
+    fn accept_subscriber(&mut self, stream: TcpStream) {
+        if stream
+            .set_nonblocking(false)
+            .and_then(|_| stream.set_timeouts(HANDSHAKE_TIMEOUT, HANDSHAKE_TIMEOUT))
+            .is_err()
+        {
+            return;
+        }
+        let mut subscription = None;
+        let socket = tungstenite::accept_hdr(stream, |request: &Request, response| {
+            let url = request.uri().to_string();
+            let authorization = request
+                .headers()
+                .get("Authorization")
+                .and_then(|value| value.to_str().ok());
+            let token = request_token(authorization, &url);
+            match self.subscription(&url, token.as_deref()) {
+                Ok(found) => {
+                    subscription = Some(found);
+                    Ok(response)
+                }
+                Err(err) => {
+                    let mut rejection =
+                        ErrorResponse::new(Some(String::from_utf8_lossy(&err.body).into_owned()));
+                    *rejection.status_mut() = tungstenite::http::StatusCode::from_u16(err.status)
+                        .unwrap_or(tungstenite::http::StatusCode::NOT_FOUND);
+                    Err(rejection)
+                }
+            }
+        })
+        .ok();
+        if let (Some(socket), Some((game, access))) = (socket, subscription) {
+            self.add_subscriber(game, access, socket);
+        }
+    }
+
+    // check that a game's events can be subscribed to
+    fn subscription(&self, url: &str, token: Option<&str>) -> Result<(u64, Access), Response> {
+        let path = url.split('?').next().unwrap_or("");
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        match segments[..] {
+            ["games", id, "events"] => {
+                match id
+                    .parse()
+                    .ok()
+                    .and_then(|id| self.games.get(&id).map(|g| (id, g)))
+                {
+                    Some((id, game)) => Ok((id, game.access(token))),
+                    None => Err(Response::error(404, "no such game")),
+                }
+            }
+            _ => Err(Response::error(404, "not found")),
+        }
+    }
+
+    // queue every pending event for the game's subscribers, dropping the ones
+    //   that have gone away or fallen too far behind
+    fn publish(&mut self) {
+        let games = &self.games;
+        let subscribers = &mut self.subscribers;
+        for (id, event) in self.events.drain(..) {
+            if !games.contains_key(&id) {
+                continue;
+            }
+            subscribers.retain_mut(|subscriber| {
+                if subscriber.game != id {
+                    return true;
+                }
+                let event = match subscriber.access {
+                    Access::Master => event.clone(),
+                    Access::Power(ref power) => event.redacted(Some(power)),
+                    Access::Public => event.redacted(None),
+                };
+                let json = serde_json::to_string(&event).expect("Failed to serialize event");
+                subscriber.events.try_send(json).is_ok()
+            });
+        }
+    }
+
+    // answer requests on an address like "127.0.0.1:8000", and WebSockets
+    //   for events on another, until the process is stopped; the WebSockets
+    //   get a listener of their own since tiny_http doesn't give access to
+    //   the socket under an upgraded request, and subscribers need to set
+    //   timeouts on theirs
+    pub fn serve(&mut self, address: &str, events_address: &str) -> Result<(), String> {
+        let http = tiny_http::Server::http(address).map_err(|err| err.to_string())?;
+        let websockets = TcpListener::bind(events_address)
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+            .map_err(|err| format!("can't listen on {}: {}", events_address, err))?;
+        loop {
+            // every waiting connection, until accepting would block
+            while let Ok((stream, _)) = websockets.accept() {
+                self.accept_subscriber(stream);
+            }
+
+            // deadlines have to be checked even when nobody's asking
+            let mut request = match http.recv_timeout(POLL_INTERVAL) {
+                Ok(Some(request)) => request,
+                Ok(None) => {
+                    self.tick();
+                    continue;
+                }
+                Err(err) => return Err(err.to_string()),
+            };
+            let header = |name: &'static str| {
+                request
+                    .headers()
+                    .iter()
+                    .find(|h| h.field.equiv(name))
+                    .map(|h| h.value.as_str().to_string())
+            };
+            let token = request_token(header("Authorization").as_deref(), request.url());
+
+            if header("Upgrade").is_some_and(|u| u.eq_ignore_ascii_case("websocket")) {
+                let message = format!("WebSockets are served on {}", events_address);
+                let _ = request
+                    .respond(tiny_http::Response::from_string(message).with_status_code(400));
+                continue;
+            }
+
+            let mut body = String::new();
+            let response = match request.as_reader().read_to_string(&mut body) {
+                Ok(_) => self.handle(
+                    request.method().as_str(),
+                    request.url(),
+                    token.as_deref(),
+                    &body,
+                ),
+                Err(_) => Response::error(400, "the body must be UTF-8"),
+            };
+            let header = tiny_http::Header::from_bytes("Content-Type", response.content_type)
+                .expect("Failed to build header");
+            let http_response = tiny_http::Response::from_data(response.body)
+                .with_status_code(response.status)
+                .with_header(header);
+            // the client hanging up isn't the server's problem
+            let _ = request.respond(http_response);
+        }
+    }
+
+    // answer a single request, made with the given token if any
+    pub fn handle(&mut self, method: &str, url: &str, token: Option<&str>, body: &str) -> Response {
+        self.tick();
+
+        let path = url.split('?').next().unwrap_or("");
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let id = match segments[..] {
+            ["games", id, ..] => id.parse().ok(),
+            _ => None,
+        };
+        // a panic answers the request with a 500 instead of bringing the
+        //   server down, and whatever the request was changing is put back
+        let backup = id
+            .and_then(|id| self.games.get(&id))
+            .filter(|_| method != "GET")
+            .map(|game| game.state.clone());
+        let response = match panic::catch_unwind(AssertUnwindSafe(|| {
+            self.respond(method, url, token, body)
+        })) {
+            Ok(response) => response,
+            Err(_) => {
+                error!(method, url; "request panicked");
+                if let (Some(id), Some(state)) = (id, backup) {
+                    if let Some(game) = self.games.get_mut(&id) {
+                        game.state.restore(state);
+                    }
+                }
+                return Response::error(500, "internal error");
+            }
+        };
+        if method == "GET" || response.status >= 400 {
+            return response;
+        }
+
+        if let (Some(id), Some(before)) = (id, backup) {
+            for event in self.games[&id].state.events_after(&before) {
+                self.events.push((id, event));
+            }
+        }
+        self.publish();
+
+        // save whichever game the request changed
+        let id = match segments[..] {
+            ["games"] => Some(self.next_id - 1),
+            _ => id,
+        };
+        match id.map(|id| self.persist(id)) {
+            Some(Err(err)) => Response::error(500, &format!("failed to save the game: {}", err)),
+            _ => response,
+        }
+    }
+
+    fn respond(&mut self, method: &str, url: &str, token: Option<&str>, body: &str) -> Response {
+        let path = url.split('?').next().unwrap_or("");
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        match (method, &segments[..]) {
+            ("POST", ["games"]) => self.create(body),
+            ("GET", ["games"]) => Response::json(
+                200,
+                self.games
+                    .iter()
+                    .map(|(id, game)| {
+                        serde_json::json!({
+                            "id": id,
+                            "map": game.map,
+                            "phase": game.state.phase,
+                            "year": game.state.year,
+                        })
+                    })
+                    .collect(),
+            ),
+            (_, ["games", id, rest @ ..]) => {
+                let map_dir = &self.map_dir;
+                let games = &mut self.games;
+                let events = &mut self.events;
+                let (id, game) = match id
+                    .parse()
+                    .ok()
+                    .and_then(|id| games.get_mut(&id).map(|g| (id, g)))
+                {
+                    Some(game) => game,
+                    None => return Response::error(404, "no such game"),
+                };
+                let access = game.access(token);
+                match (method, rest) {
+                    ("GET", []) => Response {
+                        status: 200,
+                        content_type: "application/json",
+                        body: game.view(&access).dump_json().into_bytes(),
+                    },
+                    (_, ["orders", power]) => {
+                        let power = match game.authorize(&access, token, power) {
+                            Ok(power) => power,
+                            Err(response) => return response,
+                        };
+                        match method {
+                            "POST" => {
+                                let accepted = game.state.parse(&power, body.to_string());
+                                if accepted > 0 {
+                                    events.push((
+                                        id,
+                                        Event::OrderSubmitted {
+                                            power: power.clone(),
+                                            phase: game.state.phase,
+                                            year: game.state.year,
+                                        },
+                                    ));
+                                }
+                                let submitted =
+                                    body.lines().filter(|l| !l.trim().is_empty()).count();
+                                Response::json(
+                                    200,
+                                    serde_json::json!({
+                                        "accepted": accepted,
+                                        "submitted": submitted,
+                                    }),
+                                )
+                            }
+                            "GET" => Response::json(200, game.state.pending_orders(&power).into()),
+                            "DELETE" => {
+                                game.state.clear_orders(&power);
+                                Response::json(200, serde_json::json!([]))
+                            }
+                            _ => Response::error(405, "use GET, POST or DELETE"),
+                        }
+                    }
+                    ("POST", ["ready", power]) => {
+                        let power = match game.authorize(&access, token, power) {
+                            Ok(power) => power,
+                            Err(response) => return response,
+                        };
+                        game.state.set_ready(&power, body.trim() != "false");
+                        let adjudicated = game.state.tick(&*self.clock);
+                        Response::json(
+                            200,
+                            serde_json::json!({
+                                "adjudicated": adjudicated,
+                                "phase": game.state.phase,
+                                "year": game.state.year,
+                                "deadline": game.state.deadline,
+                            }),
+                        )
+                    }
+                    ("POST", ["press", power]) => {
+                        let power = match game.authorize(&access, token, power) {
+                            Ok(power) => power,
+                            Err(response) => return response,
+                        };
+                        let message: serde_json::Value = match serde_json::from_str(body) {
+                            Ok(message) => message,
+                            Err(_) => return Response::error(400, "the body must be JSON"),
+                        };
+                        let to: Vec<Power> = match message["to"] {
+                            serde_json::Value::Null => vec![],
+                            serde_json::Value::Array(ref to)
+                                if to.iter().all(|p| p.is_string()) =>
+                            {
+                                to.iter()
+                                    .map(|p| Power::from(p.as_str().unwrap()))
+                                    .collect()
+                            }
+                            _ => return Response::error(400, "to must be a list of powers"),
+                        };
+                        match game.state.send_press(
+                            &power,
+                            &to,
+                            message["body"].as_str().unwrap_or(""),
+                            message["anonymous"].as_bool().unwrap_or(false),
+                        ) {
+                            Ok(()) => Response::json(201, serde_json::json!({})),
+                            Err(PressError::UnknownPower) => Response::error(404, "no such power"),
+                            Err(PressError::EmptyMessage) => {
+                                Response::error(400, &PressError::EmptyMessage.to_string())
+                            }
+                            Err(err) => Response::error(403, &err.to_string()),
+                        }
+                    }
+                    ("GET", ["press"]) => {
+                        let press = match access {
+                            Access::Master => game.state.press.clone(),
+                            Access::Power(ref power) => game.state.press_for(Some(power)),
+                            Access::Public => game.state.press_for(None),
+                        };
+                        Response::json(200, serde_json::to_value(press).unwrap())
+                    }
+                    ("POST", ["adjudicate"]) => {
+                        if access != Access::Master {
+                            return Response::error(403, "only the game master can adjudicate");
+                        }
+                        game.state.apply();
+                        Response::json(
+                            200,
+                            serde_json::json!({
+                                "phase": game.state.phase,
+                                "year": game.state.year,
+                            }),
+                        )
+                    }
+                    ("GET", ["results"]) => Response::json(
+                        200,
+                        serde_json::to_value(game.state.history.last())
+                            .expect("Failed to serialize results"),
+                    ),
+                    ("GET", ["map.svg"]) => {
+                        let options = RenderOptions {
+                            map_svg: Path::new(map_dir)
+                                .join(format!("{}.svg", game.map))
+                                .to_string_lossy()
+                                .into_owned(),
+                            show_legend: true,
+                            ..Default::default()
+                        };
+                        match game.view(&access).render_with(&options) {
+                            Ok(svg) => Response {
+                                status: 200,
+                                content_type: "image/svg+xml",
+                                body: svg.into_bytes(),
+                            },
+                            Err(err) => Response::error(500, &err.to_string()),
+                        }
+                    }
+                    _ => Response::error(404, "not found"),
+                }
+            }
+            _ => Response::error(404, "not found"),
+        }
+    }
+
+    fn create(&mut self, body: &str) -> Response {
+        let request = if body.trim().is_empty() {
+            serde_json::json!({})
+        } else {
+            match serde_json::from_str::<serde_json::Value>(body) {
+                Ok(request) => request,
+                Err(_) => return Response::error(400, "the body must be JSON"),
+            }
+        };
+        let map = match request["map"] {
+            serde_json::Value::Null => String::from("standard"),
+            serde_json::Value::String(ref map) => map.clone(),
+            _ => return Response::error(400, "the map must be a string"),
+        };
+        let press_rules: PressRules = match (&request["press"], &request["gunboat"]) {
+            (_, &serde_json::Value::Bool(true)) => PressRules::gunboat(),
+            (&serde_json::Value::Null, _) => PressRules::default(),
+            (rules, _) => match serde_json::from_value(rules.clone()) {
+                Ok(rules) => rules,
+                Err(_) => return Response::error(400, "press rules must be true or false"),
+            },
+        };
+        let phase_lengths: PhaseLengths = match request["phase_lengths"] {
+            serde_json::Value::Null => PhaseLengths::default(),
+            ref lengths => match serde_json::from_value(lengths.clone()) {
+                Ok(lengths) => lengths,
+                Err(_) => return Response::error(400, "phase lengths must be numbers of seconds"),
+            },
+        };
+        // map names can't be used to read files outside of map_dir
+        if map.is_empty()
+            || !map
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Response::error(400, "map names may only contain letters, digits, _ and -");
+        }
+
+        let mapfile = Path::new(&self.map_dir).join(format!("{}.csv", map));
+        let mapfile = mapfile.to_string_lossy();
+        if !Path::new(&*mapfile).is_file() {
+            return Response::error(404, "no such map");
+        }
+        let problems = Stpsyr::validate_map(&mapfile);
+        if !problems.is_empty() {
+            return Response::json(
+                422,
+                serde_json::json!({ "error": "the map is invalid", "problems": problems }),
+            );
+        }
+
+        let mut state = Stpsyr::new(&mapfile);
+        state.phase_lengths = phase_lengths;
+        state.press_rules = press_rules;
+        state.tick(&*self.clock);
+        let tokens: HashMap<Power, String> = state
+            .powers()
+            .into_iter()
+            .map(|p| (p, new_token()))
+            .collect();
+        let master_token = new_token();
+        let response = serde_json::json!({
+            "id": self.next_id,
+            "master_token": master_token,
+            "tokens": tokens
+                .iter()
+                .map(|(p, t)| (p.name.clone(), t.clone()))
+                .collect::<BTreeMap<String, String>>(),
+        });
+
+        self.games.insert(
+            self.next_id,
+            Game {
+                map,
+                state,
+                tokens,
+                master_token,
+            },
+        );
+        self.next_id += 1;
+        Response::json(201, response)
+    }
+}
+
+// a random secret that's hard to guess
+fn new_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+// the token a request was made with, from an "Authorization: Bearer <token>"
+//   header or a ?token=<token> query
+fn request_token(authorization: Option<&str>, url: &str) -> Option<String> {
+    authorization
+        .and_then(|t| t.strip_prefix("Bearer ").map(|t| t.trim().to_string()))
+        .or_else(|| {
+            let query = url.split_once('?')?.1;
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("token="))
+                .map(String::from)
+        })
+}
@@ -0,0 +1,165 @@
+use std::path::Path;
+
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, Error, OptionalExtension, Result};
+
+use stpsyr::types::*;
+
+// a Storage keeps any number of games in an SQLite database file: the
+//   current position of each game (including the orders submitted so far
+//   this phase), one row per adjudicated phase with its orders and results,
+//   and whatever metadata the application wants to keep with the game
+pub struct Storage {
+    conn: Connection,
+}
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS games (
+        id       INTEGER PRIMARY KEY,
+        -- the position as JSON, without its history
+        state    TEXT NOT NULL,
+        phase    TEXT NOT NULL,
+        year     INTEGER NOT NULL,
+        metadata TEXT
+    );
+    CREATE TABLE IF NOT EXISTS phases (
+        game_id  INTEGER NOT NULL REFERENCES games(id) ON DELETE CASCADE,
+        seq      INTEGER NOT NULL,
+        phase    TEXT NOT NULL,
+        year     INTEGER NOT NULL,
+        -- the PhaseRecord as JSON, with the resolved orders and the powers
+        -- that NMR'd
+        record   TEXT NOT NULL,
+        PRIMARY KEY (game_id, seq)
+    );
+";
+
+impl Storage {
+    // open a database file, creating it if it doesn't exist yet
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Storage> {
+        Storage::init(Connection::open(path)?)
+    }
+
+    // a database that only lasts as long as the Storage does
+    pub fn open_in_memory() -> Result<Storage> {
+        Storage::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Storage> {
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Storage { conn })
+    }
+
+    // store a game under an id, replacing what was there, history included
+    pub fn save(&mut self, id: u64, game: &Stpsyr) -> Result<()> {
+        let tx = self.conn.transaction()?;
+
+        let mut state = game.clone();
+        let history = ::std::mem::take(&mut state.history);
+        tx.execute(
+            "INSERT INTO games (id, state, phase, year) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (id) DO UPDATE
+             SET state = excluded.state, phase = excluded.phase, year = excluded.year",
+            params![
+                id as i64,
+                state.dump_json(),
+                game.phase.to_string(),
+                game.year
+            ],
+        )?;
+
+        // a game that was forked or rolled back can have fewer phases than
+        //   were stored, or different ones that were adjudicated since
+        tx.execute(
+            "DELETE FROM phases WHERE game_id = ?1 AND seq >= ?2",
+            params![id as i64, history.len() as i64],
+        )?;
+        for (seq, record) in history.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO phases (game_id, seq, phase, year, record)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT (game_id, seq) DO UPDATE
+                 SET phase = excluded.phase, year = excluded.year, record = excluded.record",
+                params![
+                    id as i64,
+                    seq as i64,
+                    record.phase.to_string(),
+                    record.year,
+                    serde_json::to_string(record).unwrap()
+                ],
+            )?;
+        }
+
+        tx.commit()
+    }
+
+    // the game stored under an id, with its full history
+    pub fn load(&self, id: u64) -> Result<Option<Stpsyr>> {
+        let state: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT state FROM games WHERE id = ?1",
+                params![id as i64],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let mut game = match state {
+            Some(state) => Stpsyr::parse_json(&state).map_err(corrupt)?,
+            None => return Ok(None),
+        };
+        game.history = self.history(id)?;
+        Ok(Some(game))
+    }
+
+    // every adjudicated phase of a game, oldest first
+    pub fn history(&self, id: u64) -> Result<Vec<PhaseRecord>> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT record FROM phases WHERE game_id = ?1 ORDER BY seq")?;
+        let records = statement.query_map(params![id as i64], |row| row.get::<_, String>(0))?;
+        records
+            .map(|record| serde_json::from_str(&record?).map_err(corrupt))
+            .collect()
+    }
+
+    // the ids of every stored game, in order
+    pub fn ids(&self) -> Result<Vec<u64>> {
+        let mut statement = self.conn.prepare("SELECT id FROM games ORDER BY id")?;
+        let ids = statement.query_map([], |row| row.get::<_, i64>(0))?;
+        ids.map(|id| id.map(|id| id as u64)).collect()
+    }
+
+    pub fn delete(&mut self, id: u64) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM games WHERE id = ?1", params![id as i64])?;
+        Ok(())
+    }
+
+    // free-form text (e.g. JSON) an application keeps alongside a game that
+    //   has already been saved
+    pub fn set_metadata(&mut self, id: u64, metadata: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE games SET metadata = ?2 WHERE id = ?1",
+            params![id as i64, metadata],
+        )?;
+        Ok(())
+    }
+
+    pub fn metadata(&self, id: u64) -> Result<Option<String>> {
+        self.conn
+            .query_row(
+                "SELECT metadata FROM games WHERE id = ?1",
+                params![id as i64],
+                |row| row.get(0),
+            )
+            .optional()
+            .map(Option::flatten)
+    }
+}
+
+// a row whose JSON doesn't parse is reported like any other column that
+//   can't be read
+fn corrupt(err: serde_json::Error) -> Error {
+    Error::FromSqlConversionFailure(0, Type::Text, Box::new(err))
+}
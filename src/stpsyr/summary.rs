@@ -0,0 +1,106 @@
+use std::fmt::Write;
+
+use stpsyr::types::*;
+
+impl Stpsyr {
+    // a plain text overview of the position: the phase, a table of SC and
+    //   unit counts, then every power's centers and units, followed by any
+    //   dislodged units and contested provinces
+    //
+    //   Spring Diplomacy 1901
+    //
+    //   power        SCs  units
+    //   Austria        3      3
+    //   ...
+    //
+    //   Austria
+    //     SCs:   bud tri vie
+    //     units: A bud, A vie, F tri
+    pub fn summary(&self) -> String {
+        let sc_counts = self.sc_counts();
+        let unit_counts = self.unit_counts();
+        let mut powers = self.powers();
+        powers.sort_by_key(|p| p.name.clone());
+
+        let mut out = String::new();
+        writeln!(out, "{} {}", self.phase, self.year).unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "{:<12}{:>5}{:>7}", "power", "SCs", "units").unwrap();
+        for power in &powers {
+            writeln!(
+                out,
+                "{:<12}{:>5}{:>7}",
+                power.name,
+                sc_counts.get(power).unwrap_or(&0),
+                unit_counts.get(power).unwrap_or(&0)
+            )
+            .unwrap();
+        }
+
+        for power in &powers {
+            // coasts are separate regions, but the center is the same one
+            let mut scs: Vec<&str> = self
+                .map
+                .iter()
+                .filter(|r| r.sc && r.owner.as_ref() == Some(power))
+                .map(|r| &r.province.name[..])
+                .collect();
+            scs.sort();
+            scs.dedup();
+            let mut units: Vec<String> = self
+                .map
+                .iter()
+                .filter_map(|r| {
+                    r.unit
+                        .as_ref()
+                        .filter(|u| u.owner == *power)
+                        .map(|u| unit_name(u, &r.province))
+                })
+                .collect();
+            units.sort_by(|a, b| a[2..].cmp(&b[2..]));
+
+            writeln!(out).unwrap();
+            writeln!(out, "{}", power.name).unwrap();
+            writeln!(out, "  SCs:   {}", scs.join(" ")).unwrap();
+            writeln!(out, "  units: {}", units.join(", ")).unwrap();
+        }
+
+        if !self.dislodged.is_empty() {
+            writeln!(out).unwrap();
+            writeln!(out, "dislodged").unwrap();
+            for (province, unit) in &self.dislodged {
+                writeln!(out, "  {} ({})", unit_name(unit, province), unit.owner.name).unwrap();
+            }
+        }
+
+        // every province a failed move was headed for is marked (unless the
+        //   moving unit was dislodged by one coming from there), including
+        //   ones whose unit held, so only the empty ones are standoffs
+        let mut contested: Vec<&str> = self
+            .contested
+            .iter()
+            .filter(|p| self.get_unit(p).is_none())
+            .map(|p| &p.name[..])
+            .collect();
+        if !contested.is_empty() {
+            contested.sort();
+            writeln!(out).unwrap();
+            writeln!(out, "contested").unwrap();
+            writeln!(out, "  {}", contested.join(" ")).unwrap();
+        }
+
+        out
+    }
+}
+
+// e.g. "A vie" or "F stp/sc"
+fn unit_name(unit: &Unit, province: &Province) -> String {
+    format!(
+        "{} {:?}",
+        match unit.unit_type {
+            UnitType::Army => "A",
+            UnitType::Fleet => "F",
+        },
+        province
+    )
+}
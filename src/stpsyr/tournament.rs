@@ -0,0 +1,241 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::panic;
+use std::path::Path;
+
+use stpsyr::types::*;
+
+// a Player is anything that can look at a position and decide on orders for
+//   a power in the current phase
+pub trait Player {
+    fn orders(&mut self, game: &Stpsyr, power: &Power) -> Vec<(Province, LegalOrder)>;
+}
+
+// how a single game ended
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    // a power controls more than half of the supply centers
+    Solo(Power),
+    // the year limit was reached; these powers were still alive
+    Draw(Vec<Power>),
+    // the engine (or a player) panicked with this message
+    Panic(String),
+}
+
+// the result of one game, with the SC counts of every power at the start of
+//   each year, the year each eliminated power lost its last unit and the
+//   number of diplomacy phases each power missed
+#[derive(Clone, Debug)]
+pub struct GameResult {
+    pub outcome: Outcome,
+    pub sc_history: Vec<(i32, HashMap<Power, u32>)>,
+    pub eliminated: Vec<(Power, i32)>,
+    pub nmrs: HashMap<Power, u32>,
+}
+
+// a Tournament plays a number of full games on a map until a solo or until
+//   max_year has been played
+pub struct Tournament {
+    pub mapfile: String,
+    pub games: usize,
+    pub max_year: i32,
+}
+
+impl Tournament {
+    pub fn new(mapfile: &str, games: usize) -> Tournament {
+        Tournament {
+            mapfile: mapfile.to_string(),
+            games,
+            max_year: 1950,
+        }
+    }
+
+    // play every game, asking new_player for a fresh player for each power at
+    //   the start of each game (the usize is the game number)
+    pub fn run<F>(&self, mut new_player: F) -> Summary
+    where
+        F: FnMut(usize, &Power) -> Box<dyn Player>,
+    {
+        let results = (0..self.games)
+            .map(|n| {
+                let game = Stpsyr::new(&self.mapfile);
+                let players = game
+                    .powers()
+                    .into_iter()
+                    .map(|p| {
+                        let player = new_player(n, &p);
+                        (p, player)
+                    })
+                    .collect();
+                self.play(game, players)
+            })
+            .collect();
+        Summary { results }
+    }
+
+    // play a single game to completion
+    pub fn play(&self, mut game: Stpsyr, mut players: Vec<(Power, Box<dyn Player>)>) -> GameResult {
+        let mut sc_history = vec![(game.year, game.sc_counts())];
+        let mut eliminated: Vec<(Power, i32)> = vec![];
+
+        let outcome = loop {
+            // engine panics shouldn't take the whole tournament down with
+            //   them, so they're reported as the outcome of the game
+            let phase = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                for (power, player) in players.iter_mut() {
+                    for (province, order) in player.orders(&game, power) {
+                        game.submit(power, province, order);
+                    }
+                }
+                game.apply();
+            }));
+            if let Err(err) = phase {
+                let message = err
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| err.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                break Outcome::Panic(message);
+            }
+
+            let sc_counts = game.sc_counts();
+            let unit_counts = game.unit_counts();
+            for (power, _) in &players {
+                if !sc_counts.contains_key(power)
+                    && !unit_counts.contains_key(power)
+                    && !eliminated.iter().any(|(p, _)| p == power)
+                {
+                    eliminated.push((power.clone(), game.year));
+                }
+            }
+
+            if game.phase == Phase::SpringDiplomacy {
+                sc_history.push((game.year, sc_counts.clone()));
+            }
+
+            if let Some(power) = game.solo_winner() {
+                break Outcome::Solo(power);
+            }
+            if game.phase == Phase::SpringDiplomacy && game.year > self.max_year {
+                break Outcome::Draw(
+                    players
+                        .iter()
+                        .map(|(p, _)| p.clone())
+                        .filter(|p| !eliminated.iter().any(|(e, _)| e == p))
+                        .collect(),
+                );
+            }
+        };
+
+        GameResult {
+            outcome,
+            sc_history,
+            eliminated,
+            nmrs: game.nmr_counts(),
+        }
+    }
+}
+
+// the collected results of a tournament
+pub struct Summary {
+    pub results: Vec<GameResult>,
+}
+
+impl Summary {
+    pub fn write_to_file<P: AsRef<Path>>(&self, output_file: P) -> io::Result<()> {
+        let mut out_file = File::create(output_file)?;
+        write!(out_file, "{}", self)
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut solos: HashMap<&Power, usize> = HashMap::new();
+        let mut draws: HashMap<&Power, usize> = HashMap::new();
+        let mut eliminations: HashMap<&Power, usize> = HashMap::new();
+        let mut nmrs: HashMap<&Power, u32> = HashMap::new();
+        let mut panics = vec![];
+        for (n, result) in self.results.iter().enumerate() {
+            match result.outcome {
+                Outcome::Solo(ref p) => *solos.entry(p).or_insert(0) += 1,
+                Outcome::Draw(ref ps) => {
+                    for p in ps {
+                        *draws.entry(p).or_insert(0) += 1;
+                    }
+                }
+                Outcome::Panic(ref message) => panics.push((n, message)),
+            }
+            for (p, _) in &result.eliminated {
+                *eliminations.entry(p).or_insert(0) += 1;
+            }
+            for (p, count) in &result.nmrs {
+                *nmrs.entry(p).or_insert(0) += count;
+            }
+        }
+
+        let mut powers: Vec<&Power> = vec![];
+        for result in &self.results {
+            for (_, counts) in &result.sc_history {
+                for p in counts.keys() {
+                    if !powers.contains(&p) {
+                        powers.push(p);
+                    }
+                }
+            }
+        }
+        powers.sort_by_key(|p| p.name.clone());
+
+        writeln!(f, "games: {}", self.results.len())?;
+        writeln!(
+            f,
+            "{:<12}{:>8}{:>8}{:>8}{:>8}",
+            "power", "solos", "draws", "elims", "nmrs"
+        )?;
+        for p in &powers {
+            writeln!(
+                f,
+                "{:<12}{:>8}{:>8}{:>8}{:>8}",
+                p.name,
+                solos.get(p).unwrap_or(&0),
+                draws.get(p).unwrap_or(&0),
+                eliminations.get(p).unwrap_or(&0),
+                nmrs.get(p).unwrap_or(&0)
+            )?;
+        }
+
+        // the average SC count of each power at the start of each year, over
+        //   the games that got that far
+        let mut years: BTreeMap<i32, (HashMap<&Power, u32>, u32)> = BTreeMap::new();
+        for result in &self.results {
+            for (year, counts) in &result.sc_history {
+                let (totals, games) = years.entry(*year).or_insert((HashMap::new(), 0));
+                for (p, count) in counts {
+                    *totals.entry(p).or_insert(0) += count;
+                }
+                *games += 1;
+            }
+        }
+        writeln!(f, "average supply centers")?;
+        write!(f, "{:<12}", "year")?;
+        for p in &powers {
+            write!(f, "{:>8}", p.name)?;
+        }
+        writeln!(f)?;
+        for (year, (totals, games)) in years {
+            write!(f, "{:<12}", year)?;
+            for p in &powers {
+                let total = totals.get(p).cloned().unwrap_or(0);
+                write!(f, "{:>8.1}", f64::from(total) / f64::from(games))?;
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(f, "panics: {}", panics.len())?;
+        for (n, message) in panics {
+            writeln!(f, "  game {}: {}", n, message)?;
+        }
+        Ok(())
+    }
+}
@@ -4,6 +4,8 @@ use std::hash;
 
 use std::collections::HashSet;
 
+use stpsyr::observer::Observers;
+
 // the only information attached to a Unit is its owner and type
 // ex. "Austrian fleet"
 #[derive(Serialize, Deserialize, Clone)]
@@ -141,6 +143,19 @@ impl fmt::Debug for MapRegion {
         )
     }
 }
+impl MapRegion {
+    // the coasts a fleet can be on, which is none unless the province is split
+    pub fn coasts(&self) -> Vec<char> {
+        let mut coasts: Vec<char> = self
+            .fleet_borders
+            .iter()
+            .filter_map(|p| p.from_coast)
+            .collect();
+        coasts.sort();
+        coasts.dedup();
+        coasts
+    }
+}
 impl cmp::PartialEq for MapRegion {
     fn eq(&self, other: &MapRegion) -> bool {
         self.province == other.province
@@ -176,9 +191,31 @@ pub struct Order {
     pub state: OrderState,
     pub id: usize,
 }
+// in the usual short notation, e.g. "bud S vie - gal"
+impl fmt::Display for Order {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.action {
+            Action::Hold => write!(f, "{:?} H", self.province),
+            Action::Move { ref to, convoyed } => write!(
+                f,
+                "{:?} - {:?}{}",
+                self.province,
+                to,
+                if convoyed { " via convoy" } else { "" }
+            ),
+            Action::SupportHold { ref to } => write!(f, "{:?} S {:?}", self.province, to),
+            Action::SupportMove { ref from, ref to } => {
+                write!(f, "{:?} S {:?} - {:?}", self.province, from, to)
+            }
+            Action::Convoy { ref from, ref to } => {
+                write!(f, "{:?} C {:?} - {:?}", self.province, from, to)
+            }
+        }
+    }
+}
 
 // utility type for Retreat, corresponding to Action for Order
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum RetreatAction {
     Disband,
     Move { to: Province },
@@ -186,27 +223,62 @@ pub enum RetreatAction {
 
 // a Retreat stores the power that ordered it, which province to retreat from,
 //   and what to do with it (disband or move)
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Retreat {
     pub owner: Power,
     pub province: Province,
     pub action: RetreatAction,
 }
+impl fmt::Display for Retreat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.action {
+            RetreatAction::Disband => write!(f, "{:?} disband", self.province),
+            RetreatAction::Move { ref to } => write!(f, "{:?} - {:?}", self.province, to),
+        }
+    }
+}
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum AdjustAction {
     Disband,
-    Build { unit_type: UnitType },
+    Build {
+        unit_type: UnitType,
+        // the coast a fleet is built on, in a region with more than one
+        #[serde(default)]
+        coast: Option<char>,
+    },
 }
 
 // a Adjust stores the power that ordered it, which province to build/destroy
 // in, and what to do there (disband or build a unit)
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Adjust {
     pub owner: Power,
     pub province: Province,
     pub action: AdjustAction,
 }
+impl fmt::Display for Adjust {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.action {
+            AdjustAction::Disband => write!(f, "disband {:?}", self.province),
+            AdjustAction::Build {
+                unit_type: UnitType::Army,
+                ..
+            } => write!(f, "build A {:?}", self.province),
+            AdjustAction::Build {
+                unit_type: UnitType::Fleet,
+                coast,
+            } => write!(
+                f,
+                "build F {:?}",
+                Province {
+                    coast: coast.or(self.province.coast),
+                    ..self.province.clone()
+                }
+            ),
+        }
+    }
+}
 
 // fairly self-explanatory
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
@@ -218,8 +290,124 @@ pub enum Phase {
     Builds,
 }
 
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                Phase::SpringDiplomacy => "Spring Diplomacy",
+                Phase::SpringRetreats => "Spring Retreats",
+                Phase::FallDiplomacy => "Fall Diplomacy",
+                Phase::FallRetreats => "Fall Retreats",
+                Phase::Builds => "Builds",
+            }
+        )
+    }
+}
+
+// what to do for a power that submits no orders in a diplomacy phase
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum NmrPolicy {
+    // every unit holds
+    #[default]
+    HoldAll,
+    // the hold and support orders from the previous diplomacy phase are
+    //   given again wherever they're still legal; everything else holds
+    RepeatLast,
+}
+
+// a PhaseRecord is kept for every adjudicated diplomacy phase, storing the
+//   resolved orders and which powers didn't submit any (NMR'd)
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PhaseRecord {
+    pub phase: Phase,
+    pub year: i32,
+    pub orders: Vec<Order>,
+    pub nmrs: Vec<Power>,
+}
+
+// how many seconds each kind of phase lasts once it starts; a phase without
+//   a length only ends when every power is ready (or someone calls apply)
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct PhaseLengths {
+    pub diplomacy: Option<u64>,
+    pub retreats: Option<u64>,
+    pub builds: Option<u64>,
+}
+
+// a Message is a piece of press; it goes to every power if to is empty (a
+//   broadcast), and its sender is only known to the sender if it's
+//   anonymous (gray press)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Message {
+    // None when shown to someone who isn't allowed to know the sender
+    pub from: Option<Power>,
+    pub to: Vec<Power>,
+    pub body: String,
+    pub anonymous: bool,
+    // when it was sent
+    pub phase: Phase,
+    pub year: i32,
+}
+
+// which kinds of press are allowed; a gunboat game allows none at all
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct PressRules {
+    pub private: bool,
+    pub broadcast: bool,
+    pub gray: bool,
+}
+
+impl Default for PressRules {
+    fn default() -> PressRules {
+        PressRules {
+            private: true,
+            broadcast: true,
+            gray: false,
+        }
+    }
+}
+
+impl PressRules {
+    pub fn gunboat() -> PressRules {
+        PressRules {
+            private: false,
+            broadcast: false,
+            gray: false,
+        }
+    }
+}
+
+// why a message couldn't be sent
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PressError {
+    NoPrivatePress,
+    NoBroadcastPress,
+    NoGrayPress,
+    UnknownPower,
+    EmptyMessage,
+}
+impl fmt::Display for PressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                PressError::NoPrivatePress => "private press isn't allowed in this game",
+                PressError::NoBroadcastPress => "broadcast press isn't allowed in this game",
+                PressError::NoGrayPress => "anonymous press isn't allowed in this game",
+                PressError::UnknownPower => "no such power",
+                PressError::EmptyMessage => "the message is empty",
+            }
+        )
+    }
+}
+
 // this is the main struct (duh)
-#[derive(Serialize, Deserialize)]
+// it is Clone so that a position can be forked for what-if analysis
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Stpsyr {
     pub map: Vec<MapRegion>,
     pub orders: Vec<Order>,
@@ -227,7 +415,43 @@ pub struct Stpsyr {
     pub adjusts: Vec<Adjust>,
     pub dependencies: Vec<usize>,
     pub dislodged: Vec<(Province, Unit)>,
+    // where each dislodged unit's attacker came from, by the dislodged unit's
+    //   province, unless the attacker was convoyed
+    #[serde(default)]
+    pub attacked_from: Vec<(Province, Province)>,
     pub contested: HashSet<Province>,
     pub phase: Phase,
     pub year: i32,
+    #[serde(default)]
+    pub history: Vec<PhaseRecord>,
+    // the powers that didn't order anything in a retreat or builds phase
+    //   they had to order in; diplomacy phases keep theirs in history
+    #[serde(default)]
+    pub nmr_log: Vec<(Phase, i32, Power)>,
+    #[serde(default)]
+    pub nmr_policy: NmrPolicy,
+    #[serde(default)]
+    pub phase_lengths: PhaseLengths,
+    // when the current phase will be adjudicated, in seconds since the epoch
+    #[serde(default)]
+    pub deadline: Option<u64>,
+    // the powers that are done ordering for the current phase
+    #[serde(default)]
+    pub ready: Vec<Power>,
+    #[serde(default)]
+    pub press: Vec<Message>,
+    #[serde(default)]
+    pub press_rules: PressRules,
+    // these aren't part of the position, so they aren't saved with it
+    #[serde(skip)]
+    pub observers: Observers,
+}
+
+// a LegalOrder is one thing a province may be ordered to do in the current
+//   phase, as returned by legal_orders()
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum LegalOrder {
+    Order(Action),
+    Retreat(RetreatAction),
+    Adjust(AdjustAction),
 }
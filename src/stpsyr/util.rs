@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use stpsyr::observer::EngineEvent;
 use stpsyr::types::*;
 
 impl Stpsyr {
@@ -13,6 +14,19 @@ impl Stpsyr {
         self.map.iter().find(|r| r.province == *province)
     }
 
+    // get every power that starts with a home center, in map order
+    pub fn powers(&self) -> Vec<Power> {
+        let mut powers: Vec<Power> = vec![];
+        for r in &self.map {
+            if let Some(ref p) = r.home_power {
+                if !powers.contains(p) {
+                    powers.push(p.clone());
+                }
+            }
+        }
+        powers
+    }
+
     // get counts of SCs owned for each power
     pub fn sc_counts(&self) -> HashMap<Power, u32> {
         let mut counts = HashMap::new();
@@ -45,15 +59,84 @@ impl Stpsyr {
         counts
     }
 
+    // get the number of builds (positive) or disbands (negative) each power
+    //   is due, i.e. its SC count minus its unit count
+    pub fn adjust_deltas(&self) -> HashMap<Power, i32> {
+        let sc_counts = self.sc_counts();
+        let mut deltas: HashMap<Power, i32> = sc_counts
+            .iter()
+            .map(|(p, &c)| (p.clone(), c as i32))
+            .collect();
+        for (p, c) in self.unit_counts() {
+            *deltas.entry(p).or_insert(0) -= c as i32;
+        }
+        deltas
+    }
+
+    // get the power that controls more than half of the supply centers, if
+    //   any; that's a solo, and the game is over
+    pub fn solo_winner(&self) -> Option<Power> {
+        let total_scs = self.map.iter().filter(|r| r.sc).count() as u32;
+        self.sc_counts()
+            .into_iter()
+            .find(|&(_, c)| c * 2 > total_scs)
+            .map(|(p, _)| p)
+    }
+
+    // get the number of moves a unit of the given type needs to reach every
+    //   province it can reach from a starting province, ignoring coasts
+    pub fn distances(&self, from: &Province, unit_type: UnitType) -> HashMap<String, usize> {
+        let mut distances = HashMap::new();
+        distances.insert(from.name.clone(), 0);
+        let mut frontier = vec![from.clone()];
+        let mut distance = 0;
+        while !frontier.is_empty() {
+            distance += 1;
+            let mut next = vec![];
+            for province in &frontier {
+                let region = self.get_region(province).unwrap();
+                let borders = match unit_type {
+                    UnitType::Army => &region.army_borders,
+                    UnitType::Fleet => &region.fleet_borders,
+                };
+                for border in borders {
+                    if !distances.contains_key(&border.name) {
+                        distances.insert(border.name.clone(), distance);
+                        next.push(Province::from(border.name.clone()));
+                    }
+                }
+            }
+            frontier = next;
+        }
+        distances
+    }
+
     pub fn next_phase(&mut self) {
+        // the new phase gets a deadline of its own the next time the clock
+        //   is checked, and everyone has to say they're ready again
+        self.deadline = None;
+        self.ready.clear();
+
         // update ownership
+        let mut captured: Vec<EngineEvent> = vec![];
         for ref mut r in &mut self.map {
             if !r.sc || self.phase == Phase::FallDiplomacy || self.phase == Phase::FallRetreats {
                 if let Some(ref unit) = r.unit {
+                    if r.sc && r.owner.as_ref() != Some(&unit.owner) {
+                        captured.push(EngineEvent::ScCaptured {
+                            province: r.province.clone(),
+                            from: r.owner.clone(),
+                            to: unit.owner.clone(),
+                        });
+                    }
                     r.owner = Some(unit.owner.clone());
                 }
             }
         }
+        for event in captured {
+            self.notify(event);
+        }
+        let from = (self.phase, self.year);
 
         self.phase = match self.phase {
             Phase::SpringDiplomacy => {
@@ -69,6 +152,8 @@ impl Stpsyr {
                     if self.sc_counts() != self.unit_counts() {
                         Phase::Builds
                     } else {
+                        // no builds, so the year is over
+                        self.year += 1;
                         Phase::SpringDiplomacy
                     }
                 } else {
@@ -80,5 +165,15 @@ impl Stpsyr {
                 Phase::SpringDiplomacy
             }
         };
+        info!(
+            phase:% = from.0, year = from.1;
+            "adjudicated; next is {} {}",
+            self.phase,
+            self.year
+        );
+        self.notify(EngineEvent::PhaseChanged {
+            from,
+            to: (self.phase, self.year),
+        });
     }
 }
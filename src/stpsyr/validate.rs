@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use stpsyr::types::*;
+
+extern crate csv;
+
+impl Stpsyr {
+    // check a map file for problems that Stpsyr::new would either panic on or
+    //   silently accept, returning a description of each one (so an empty
+    //   list means the map is fine)
+    pub fn validate_map(mapfile: &str) -> Vec<String> {
+        let mut problems = vec![];
+        let mut reader = match csv::Reader::from_path(mapfile) {
+            Ok(reader) => reader,
+            Err(err) => return vec![format!("can't read {}: {}", mapfile, err)],
+        };
+
+        // every row that parsed, keyed by name (including the coast), along
+        //   with its line number for error messages
+        let mut rows: Vec<(u64, String, Vec<String>, Vec<String>)> = vec![];
+        for (n, row) in reader
+            .deserialize::<(
+                String,
+                bool,
+                Option<String>,
+                Option<String>,
+                String,
+                String,
+                usize,
+                usize,
+            )>()
+            .enumerate()
+        {
+            // the header is line 1
+            let line = n as u64 + 2;
+            let row = match row {
+                Ok(row) => row,
+                Err(err) => {
+                    problems.push(format!("line {}: {}", line, err));
+                    continue;
+                }
+            };
+            if rows.iter().any(|r| r.1 == row.0) {
+                problems.push(format!("line {}: {} is listed twice", line, row.0));
+            }
+            match (&row.2, &row.3) {
+                (None, Some(_)) => {
+                    problems.push(format!("line {}: {} has a unit but no owner", line, row.0))
+                }
+                (_, Some(unit_type)) if unit_type != "Army" && unit_type != "Fleet" => problems
+                    .push(format!(
+                        "line {}: unit type must be Army or Fleet, not {}",
+                        line, unit_type
+                    )),
+                (_, Some(unit_type)) if unit_type == "Army" && row.5.trim().is_empty() => problems
+                    .push(format!(
+                        "line {}: army in {}, which armies can't reach",
+                        line, row.0
+                    )),
+                (_, Some(unit_type)) if unit_type == "Fleet" && row.4.trim().is_empty() => problems
+                    .push(format!(
+                        "line {}: fleet in {}, which fleets can't reach",
+                        line, row.0
+                    )),
+                _ => {}
+            }
+            rows.push((
+                line,
+                row.0,
+                row.4.split_whitespace().map(String::from).collect(),
+                row.5.split_whitespace().map(String::from).collect(),
+            ));
+        }
+
+        // borders have to name a province (and coast) on the map, and go
+        //   both ways; fleets border a province with coasts from one coast
+        //   in particular, so the way back is looked up ignoring coasts
+        let strip = |name: &str| name.split('/').next().unwrap().to_string();
+        let mut fleet_borders: HashMap<String, Vec<String>> = HashMap::new();
+        for row in &rows {
+            fleet_borders
+                .entry(strip(&row.1))
+                .or_default()
+                .extend(row.2.iter().map(|b| strip(b)));
+        }
+        for (line, name, fleet, army) in &rows {
+            for border in fleet {
+                if !rows.iter().any(|r| r.1 == *border) {
+                    problems.push(format!("line {}: unknown fleet border {}", line, border));
+                } else if !fleet_borders[&strip(border)].contains(&strip(name)) {
+                    problems.push(format!(
+                        "line {}: {} borders {} for fleets, but not the other way around",
+                        line, name, border
+                    ));
+                }
+            }
+            for border in army {
+                match rows.iter().find(|r| strip(&r.1) == *border) {
+                    None => problems.push(format!("line {}: unknown army border {}", line, border)),
+                    Some(r) if !r.3.contains(&strip(name)) => problems.push(format!(
+                        "line {}: {} borders {} for armies, but not the other way around",
+                        line, name, border
+                    )),
+                    _ => {}
+                }
+            }
+        }
+
+        problems
+    }
+}
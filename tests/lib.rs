@@ -16,12 +16,27 @@
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
+extern crate gif;
+extern crate log;
+extern crate rusqlite;
+extern crate serde_json;
 extern crate stpsyr;
+extern crate tungstenite;
+use stpsyr::tournament::{Outcome, Player, Tournament};
 use stpsyr::*;
 
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
+// a fresh directory for a test's files, named after the test and this run
+//   so that tests (and test runs) running at the same time don't clash
+fn test_dir(test: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("stpsyr-{}-{}", std::process::id(), test));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
 fn test_from_file(filename: &str) {
     let err_msg = "error parsing test cases";
     let f = File::open(filename).expect(err_msg);
@@ -96,3 +111,1689 @@ fn test_datc_6e() {
 fn test_datc_6f() {
     test_from_file("tests/datc-6.f.txt");
 }
+#[test]
+fn test_datc_6h() {
+    test_from_file("tests/datc-6.h.txt");
+}
+
+// the moves legal_orders offers a unit, e.g. "Move { to: tys, convoyed: false }"
+fn moves(s: &Stpsyr, province: &str) -> Vec<String> {
+    s.legal_orders(&Province::from(province))
+        .iter()
+        .map(|o| format!("{:?}", o))
+        .filter(|o| o.starts_with("Order(Move"))
+        .collect()
+}
+
+#[test]
+fn test_map_ion_borders_tys() {
+    let mut s = Stpsyr::new("data/standard.csv");
+    s.parse(&Power::from("Italy"), String::from("F nap-ion"));
+    s.apply();
+    assert!(moves(&s, "ion").contains(&String::from("Order(Move { to: tys, convoyed: false })")));
+}
+
+#[test]
+fn test_map_mos_borders_lvn() {
+    let s = Stpsyr::new("data/standard.csv");
+    assert!(moves(&s, "mos").contains(&String::from("Order(Move { to: lvn, convoyed: false })")));
+}
+
+#[test]
+fn test_map_par_does_not_border_mar() {
+    let s = Stpsyr::new("data/standard.csv");
+    assert!(!moves(&s, "par").contains(&String::from("Order(Move { to: mar, convoyed: false })")));
+}
+
+#[test]
+fn test_map_pic_does_not_border_nth() {
+    let mut s = Stpsyr::new("data/standard.csv");
+    s.parse(&Power::from("France"), String::from("F bre-pic"));
+    s.apply();
+    assert!(!moves(&s, "pic").contains(&String::from("Order(Move { to: nth, convoyed: false })")));
+}
+
+#[test]
+fn test_map_stp_borders_nwy_for_armies() {
+    let mut s = Stpsyr::new("data/standard.csv");
+    s.parse(
+        &Power::from("Russia"),
+        String::from("F stp/sc-bot\nA mos-stp"),
+    );
+    s.apply();
+    assert!(moves(&s, "stp").contains(&String::from("Order(Move { to: nwy, convoyed: false })")));
+}
+
+#[test]
+fn test_fall_without_builds_starts_next_year() {
+    let mut s = Stpsyr::new("data/standard.csv");
+    s.apply();
+    s.apply();
+    assert_eq!(s.phase, Phase::SpringDiplomacy);
+    assert_eq!(s.year, 1902);
+}
+
+#[test]
+fn test_what_if_leaves_original_untouched() {
+    let s = Stpsyr::new("data/standard.csv");
+    let fork = s.what_if(&[
+        (Power::from("Italy"), String::from("A ven-tyr")),
+        (Power::from("Austria"), String::from("A vie-gal")),
+    ]);
+
+    assert_eq!(format!("{:?}", fork.get_unit(&Province::from("tyr")).unwrap()), "Army Italy");
+    assert_eq!(format!("{:?}", fork.get_unit(&Province::from("gal")).unwrap()), "Army Austria");
+    assert_eq!(fork.phase, Phase::FallDiplomacy);
+
+    assert!(s.get_unit(&Province::from("tyr")).is_none());
+    assert!(s.get_unit(&Province::from("ven")).is_some());
+    assert_eq!(s.phase, Phase::SpringDiplomacy);
+}
+
+#[test]
+fn test_legal_orders() {
+    let mut s = Stpsyr::new("data/standard.csv");
+    let legal = s.legal_orders(&Province::from("lon"));
+    let debug: Vec<String> = legal.iter().map(|o| format!("{:?}", o)).collect();
+    assert!(debug.contains(&String::from("Order(Hold)")));
+    assert!(debug.contains(&String::from("Order(Move { to: eng, convoyed: false })")));
+    assert!(debug.contains(&String::from("Order(SupportMove { from: lvp, to: wal })")));
+    assert!(!debug.contains(&String::from("Order(Move { to: pic, convoyed: false })")));
+
+    s.parse(
+        &Power::from("England"),
+        String::from("F lon-nth\nA lvp-yor"),
+    );
+    s.apply();
+
+    let yor: Vec<String> = s
+        .legal_orders(&Province::from("yor"))
+        .iter()
+        .map(|o| format!("{:?}", o))
+        .collect();
+    assert!(yor.contains(&String::from("Order(Move { to: nwy, convoyed: true })")));
+    let nth: Vec<String> = s
+        .legal_orders(&Province::from("nth"))
+        .iter()
+        .map(|o| format!("{:?}", o))
+        .collect();
+    assert!(nth.contains(&String::from("Order(Convoy { from: yor, to: nwy })")));
+    assert!(s.legal_orders(&Province::from("lvp")).is_empty());
+}
+
+#[test]
+fn test_no_builds_in_lost_home_centers() {
+    let mut s = Stpsyr::new("data/standard.csv");
+    let italy = Power::from("Italy");
+    s.parse(&Power::from("Austria"), String::from("A vie-tyr"));
+    s.parse(&italy, String::from("F nap-ion"));
+    s.apply();
+    s.parse(
+        &Power::from("Austria"),
+        String::from("F tri-ven\nA tyr S F tri-ven"),
+    );
+    s.parse(&italy, String::from("F ion-tun"));
+    s.apply();
+    s.apply();
+    s.apply();
+
+    // Austria leaves ven empty, but it still owns it
+    s.parse(&Power::from("Austria"), String::from("F ven-apu"));
+    s.apply();
+    s.apply();
+    assert_eq!(s.phase, Phase::Builds);
+    assert_eq!(s.year, 1902);
+
+    let provinces = s.orderable_provinces(&italy);
+    assert!(provinces.contains(&Province::from("nap")));
+    assert!(!provinces.contains(&Province::from("ven")));
+    assert!(s.legal_orders(&Province::from("ven")).is_empty());
+    assert_eq!(s.parse(&italy, String::from("build A ven")), 0);
+    assert_eq!(s.parse(&italy, String::from("build A nap")), 1);
+}
+
+#[test]
+fn test_fleet_builds_on_split_coasts() {
+    let mut s = Stpsyr::new("data/standard.csv");
+    let russia = Power::from("Russia");
+    s.parse(&russia, String::from("F stp/sc-bot"));
+    s.apply();
+    s.parse(&russia, String::from("F bot-swe"));
+    s.apply();
+    assert_eq!(s.phase, Phase::Builds);
+
+    let stp: Vec<String> = s
+        .legal_orders(&Province::from("stp"))
+        .iter()
+        .map(|o| format!("{:?}", o))
+        .collect();
+    assert_eq!(
+        stp,
+        vec![
+            "Adjust(Build { unit_type: Army, coast: None })",
+            "Adjust(Build { unit_type: Fleet, coast: Some('n') })",
+            "Adjust(Build { unit_type: Fleet, coast: Some('s') })",
+        ]
+    );
+    assert_eq!(s.parse(&russia, String::from("build F stp")), 0);
+    assert_eq!(s.parse(&russia, String::from("build F stp/nc")), 1);
+    s.apply();
+
+    assert_eq!(
+        format!("{:?}", s.get_unit(&Province::from("stp")).unwrap()),
+        "Fleet Russia"
+    );
+    assert_eq!(
+        s.get_region(&Province::from("stp")).unwrap().province.coast,
+        Some('n')
+    );
+    let moves: Vec<String> = s
+        .legal_orders(&Province::from("stp"))
+        .iter()
+        .map(|o| format!("{:?}", o))
+        .collect();
+    assert!(moves.contains(&String::from("Order(Move { to: bar, convoyed: false })")));
+    assert!(!moves.contains(&String::from("Order(Move { to: bot, convoyed: false })")));
+}
+
+#[test]
+fn test_bots_play_full_years() {
+    let powers = [
+        "Austria", "England", "France", "Germany", "Italy", "Russia", "Turkey",
+    ];
+    let mut s = Stpsyr::new("data/standard.csv");
+    let mut random = bots::RandomBot::new(0);
+    let mut greedy = bots::GreedyBot::new();
+    while s.year < 1905 {
+        for (i, power) in powers.iter().enumerate() {
+            let power = Power::from(*power);
+            let orders = if i % 2 == 0 {
+                random.orders(&s, &power)
+            } else {
+                greedy.orders(&s, &power)
+            };
+            for (province, order) in orders {
+                s.submit(&power, province, order);
+            }
+        }
+        s.apply();
+    }
+
+    assert_eq!(s.phase, Phase::SpringDiplomacy);
+    assert!(s.sc_counts().values().sum::<u32>() <= 34);
+    assert!(s.unit_counts().values().sum::<u32>() <= 34);
+}
+
+#[test]
+fn test_tournament() {
+    let mut tournament = Tournament::new("data/standard.csv", 2);
+    tournament.max_year = 1903;
+    let summary = tournament.run(|n, power| -> Box<dyn Player> {
+        if power.name == "Russia" {
+            Box::new(bots::GreedyBot::new())
+        } else {
+            Box::new(bots::RandomBot::new(n as u64))
+        }
+    });
+
+    assert_eq!(summary.results.len(), 2);
+    for result in &summary.results {
+        assert!(matches!(result.outcome, Outcome::Draw(_)));
+        assert_eq!(result.sc_history.first().unwrap().0, 1901);
+        assert_eq!(result.sc_history.last().unwrap().0, 1904);
+    }
+    assert!(summary.to_string().starts_with("games: 2\n"));
+
+    let dir = test_dir("tournament");
+    summary.write_to_file(dir.join("summary.txt")).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.join("summary.txt")).unwrap(),
+        summary.to_string()
+    );
+    assert!(summary
+        .write_to_file(dir.join("missing").join("summary.txt"))
+        .is_err());
+}
+
+#[test]
+fn test_tournament_summary() {
+    use std::collections::HashMap;
+    use stpsyr::tournament::{GameResult, Summary};
+
+    let counts = |italy: u32, turkey: u32| {
+        let mut counts = HashMap::new();
+        counts.insert(Power::from("Italy"), italy);
+        counts.insert(Power::from("Turkey"), turkey);
+        counts
+    };
+    let result = |sc_history| GameResult {
+        outcome: Outcome::Draw(vec![Power::from("Italy"), Power::from("Turkey")]),
+        sc_history,
+        eliminated: vec![],
+        nmrs: HashMap::new(),
+    };
+    let summary = Summary {
+        results: vec![
+            result(vec![(1901, counts(3, 3)), (1902, counts(4, 5))]),
+            result(vec![
+                (1901, counts(3, 3)),
+                (1902, counts(5, 4)),
+                (1903, counts(6, 2)),
+            ]),
+        ],
+    };
+    // the last year is only averaged over the game that got that far
+    assert!(summary.to_string().contains(concat!(
+        "average supply centers\n",
+        "year           Italy  Turkey\n",
+        "1901             3.0     3.0\n",
+        "1902             4.5     4.5\n",
+        "1903             6.0     2.0\n",
+    )));
+}
+
+#[test]
+fn test_nmr_repeat_last() {
+    let mut s = Stpsyr::new("data/standard.csv");
+    s.nmr_policy = NmrPolicy::RepeatLast;
+
+    s.parse(&Power::from("Austria"), String::from("A vie-gal"));
+    s.parse(&Power::from("Russia"), String::from("A mos-ukr"));
+    s.apply();
+    assert_eq!(s.history.len(), 1);
+    assert!(!s.history[0].nmrs.contains(&Power::from("Austria")));
+    assert!(s.history[0].nmrs.contains(&Power::from("England")));
+
+    s.parse(
+        &Power::from("Austria"),
+        String::from("A gal H\nA bud S gal"),
+    );
+    s.parse(&Power::from("Russia"), String::from("A war H"));
+    s.apply();
+    assert_eq!(s.phase, Phase::SpringDiplomacy);
+
+    // Austria misses the spring, so bud keeps supporting gal
+    s.parse(
+        &Power::from("Russia"),
+        String::from("A war-gal\nA ukr S war-gal"),
+    );
+    s.apply();
+    assert!(s.history[2].nmrs.contains(&Power::from("Austria")));
+    assert_eq!(
+        format!("{:?}", s.get_unit(&Province::from("gal")).unwrap()),
+        "Army Austria"
+    );
+    assert_eq!(s.nmr_counts().get(&Power::from("Austria")), Some(&1));
+    assert_eq!(s.nmr_counts().get(&Power::from("England")), Some(&3));
+    assert_eq!(s.nmr_counts().get(&Power::from("Russia")), None);
+}
+
+#[test]
+fn test_render_orders() {
+    let mut s = Stpsyr::new("data/standard.csv");
+    assert!(!s.render().unwrap().contains(r#"<g id="orders""#));
+
+    s.parse(&Power::from("France"), String::from("A par-bur\nA mar-bur"));
+    s.parse(&Power::from("Germany"), String::from("A mun H"));
+    s.apply();
+
+    let svg = s.render().unwrap();
+    assert!(svg.contains(r#"<g id="orders""#));
+    assert_eq!(svg.matches("<line").count(), 2);
+    assert_eq!(svg.matches(r#"stroke-opacity="0.4""#).count(), 2);
+    assert_eq!(svg.matches("<circle").count(), 1);
+}
+
+#[test]
+fn test_render_retreats() {
+    let mut s = Stpsyr::new("data/standard.csv");
+    s.parse(&Power::from("France"), String::from("A par-bur"));
+    s.parse(
+        &Power::from("Germany"),
+        String::from("A mun-ruh\nA ber-mun"),
+    );
+    s.apply();
+    s.parse(&Power::from("France"), String::from("A mar-gas\nF bre-gas"));
+    s.parse(
+        &Power::from("Germany"),
+        String::from("A ruh-bur\nA mun S ruh-bur"),
+    );
+    s.apply();
+    assert_eq!(s.phase, Phase::FallRetreats);
+
+    // bur can retreat to par, pic and bel but not to gas (contested) or ruh
+    //   (where its attacker came from)
+    let svg = s.render().unwrap();
+    let retreats = &svg[svg.find(r#"<g id="retreats""#).unwrap()..];
+    assert_eq!(retreats.matches("<circle").count(), 3);
+    assert_eq!(retreats.matches(r##"stroke="#CC0000""##).count(), 3);
+}
+
+#[test]
+fn test_render_options() {
+    let mut s = Stpsyr::new("data/standard.csv");
+    // powers missing from the palette shouldn't make rendering panic
+    let par = s
+        .map
+        .iter()
+        .position(|r| r.province == Province::from("par"))
+        .unwrap();
+    s.map[par].owner = Some(Power::from("Atlantis"));
+
+    let mut options = rendering::RenderOptions::default();
+    options
+        .palette
+        .insert(Power::from("France"), String::from("#123456"));
+    options.show_labels = false;
+    options.size = Some((451, 387));
+
+    let svg = s.render_with(&options).unwrap();
+    assert!(svg.starts_with(r#"<svg width="451" height="387""#));
+    assert!(svg.contains(r#"<g display="none" id="Textes">"#));
+    assert!(svg.contains("#123456"));
+    assert!(!svg.contains("#0766B9"));
+    assert!(svg.contains(&options.default_color));
+}
+
+#[test]
+fn test_render_recolors_by_exact_id() {
+    let artwork = r##"<svg width="10" height="10" xmlns="http://www.w3.org/2000/svg">
+<g id="spa_nc"><path d="M0 0" fill="#111111"/></g>
+<g
+    id="spa"><path d="M0 0"
+        style="stroke:black;fill:#222222"/>
+    <g><path fill="#333333" d="M0 0"/></g></g>
+<g id="por" fill="none"><path d="M0 0" fill="#444444"/></g>
+</svg>
+"##;
+    let path = test_dir("render-recolors").join("artwork.svg");
+    std::fs::write(&path, artwork).unwrap();
+
+    let mut s = Stpsyr::new("data/standard.csv");
+    for r in s.map.iter_mut() {
+        if r.province == Province::from("spa") || r.province == Province::from("por") {
+            r.owner = Some(Power::from("France"));
+        }
+    }
+    let mut palette = std::collections::HashMap::new();
+    palette.insert(Power::from("France"), String::from("#0000FF"));
+    let options = rendering::RenderOptions {
+        map_svg: path.to_str().unwrap().to_string(),
+        palette,
+        ..Default::default()
+    };
+
+    let svg = s.render_with(&options).unwrap();
+    assert!(svg.contains(r##"<g id="spa_nc"><path d="M0 0" fill="#111111"/></g>"##));
+    assert!(svg.contains(r##"style="stroke:black;fill:#0000FF""##));
+    assert!(svg.contains(r##"<path fill="#0000FF" d="M0 0"/>"##));
+    assert!(svg.contains(r##"<g id="por" fill="none"><path d="M0 0" fill="#0000FF"/>"##));
+}
+
+#[test]
+fn test_render_png() {
+    let s = Stpsyr::new("data/standard.csv");
+    let options = rendering::RenderOptions {
+        size: Some((451, 387)),
+        ..Default::default()
+    };
+    let png = s.render_png_with(&options).unwrap();
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    // the IHDR chunk holds the width and height
+    assert_eq!(&png[16..20], &451u32.to_be_bytes());
+    assert_eq!(&png[20..24], &387u32.to_be_bytes());
+}
+
+#[test]
+fn test_render_errors() {
+    let s = Stpsyr::new("data/standard.csv");
+    let missing = rendering::RenderOptions {
+        map_svg: String::from("data/missing.svg"),
+        ..Default::default()
+    };
+    assert!(matches!(
+        s.render_with(&missing),
+        Err(rendering::RenderError::MissingArtwork(..))
+    ));
+    let invalid = rendering::RenderOptions {
+        map_svg: String::from("data/standard.csv"),
+        ..Default::default()
+    };
+    assert!(matches!(
+        s.render_with(&invalid),
+        Err(rendering::RenderError::InvalidArtwork(_))
+    ));
+    assert!(s.render_png_with(&invalid).is_err());
+
+    // colors have to be like "#B19517", since they're lightened and darkened
+    let mut named = rendering::RenderOptions::default();
+    named
+        .palette
+        .insert(Power::from("France"), String::from("blue"));
+    assert_eq!(
+        s.render_with(&named),
+        Err(rendering::RenderError::InvalidColor(
+            String::from("France"),
+            String::from("blue")
+        ))
+    );
+    assert!(s.render_png_with(&named).is_err());
+    let short = rendering::RenderOptions {
+        default_color: String::from("#fff"),
+        ..Default::default()
+    };
+    assert!(short.check_colors().is_err());
+    assert!(rendering::RenderOptions::default().check_colors().is_ok());
+}
+
+#[test]
+fn test_render_replay() {
+    let mut positions = vec![Stpsyr::new("data/standard.csv")];
+    let next = positions[0].what_if(&[(Power::from("France"), String::from("mar-spa"))]);
+    positions.push(next);
+    // France takes spa and builds in mar
+    let mut builds = positions[1].what_if(&[]);
+    assert_eq!(builds.phase, Phase::Builds);
+    builds.parse(&Power::from("France"), String::from("build A mar"));
+    assert!(builds.render().unwrap().contains(r#"<g id="adjusts""#));
+    positions.push(builds);
+
+    let options = rendering::RenderOptions {
+        size: Some((90, 77)),
+        ..Default::default()
+    };
+    let gif = rendering::render_replay(&positions, &options, 100).unwrap();
+    let mut decoder = gif::DecodeOptions::new().read_info(&gif[..]).unwrap();
+    assert_eq!((decoder.width(), decoder.height()), (90, 77));
+    let mut delays = vec![];
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        delays.push(frame.delay);
+    }
+    assert_eq!(delays, vec![100, 100, 300]);
+
+    // the last frame's delay can't overflow
+    let gif = rendering::render_replay(&positions[..1], &options, u16::MAX).unwrap();
+    let mut decoder = gif::DecodeOptions::new().read_info(&gif[..]).unwrap();
+    assert_eq!(decoder.read_next_frame().unwrap().unwrap().delay, u16::MAX);
+
+    let options = rendering::RenderOptions {
+        size: Some((70000, 10)),
+        ..Default::default()
+    };
+    assert_eq!(
+        rendering::render_replay(&positions[..1], &options, 100),
+        Err(rendering::RenderError::TooLarge(70000, 10))
+    );
+}
+
+#[test]
+fn test_render_legend() {
+    let mut s = Stpsyr::new("data/standard.csv");
+    assert!(!s.render().unwrap().contains(r#"<g id="legend""#));
+
+    let options = rendering::RenderOptions {
+        show_legend: true,
+        ..Default::default()
+    };
+    let svg = s.render_with(&options).unwrap();
+    assert!(svg.contains(r#"<g id="legend""#));
+    assert!(svg.contains(">Spring Diplomacy 1901</text>"));
+    assert!(!svg.contains("+/-"));
+
+    // France takes Spain and Portugal, Russia loses Sevastopol to Turkey
+    s.parse(&Power::from("France"), String::from("mar-spa\nbre-mao"));
+    s.parse(&Power::from("Turkey"), String::from("ank-bla\nsmy-arm"));
+    s.apply();
+    s.parse(&Power::from("France"), String::from("mao-por"));
+    s.parse(
+        &Power::from("Turkey"),
+        String::from("arm-sev\nbla s arm-sev"),
+    );
+    s.parse(&Power::from("Russia"), String::from("sev h"));
+    s.apply();
+    s.apply();
+    assert_eq!(s.phase, Phase::Builds);
+    assert_eq!(s.adjust_deltas()[&Power::from("France")], 2);
+
+    let svg = s.render_with(&options).unwrap();
+    assert!(svg.contains(">Builds 1901</text>"));
+    assert!(svg.contains(">France</text>"));
+    assert!(svg.contains(">+2</text>"));
+}
+
+#[test]
+fn test_summary() {
+    let mut s = Stpsyr::new("data/standard.csv");
+    let summary = s.summary();
+    assert!(summary.starts_with("Spring Diplomacy 1901\n"));
+    assert!(summary.contains("\nAustria         3      3\n"));
+    assert!(summary
+        .contains("\nRussia\n  SCs:   mos sev stp war\n  units: A mos, F sev, F stp/sc, A war\n"));
+    assert!(!summary.contains("dislodged"));
+
+    s.parse(&Power::from("Germany"), String::from("mun-bur\nber-sil"));
+    s.parse(&Power::from("France"), String::from("par-bur"));
+    s.parse(
+        &Power::from("Austria"),
+        String::from("vie-gal\nbud s vie-gal"),
+    );
+    s.parse(&Power::from("Russia"), String::from("war-gal"));
+    s.apply();
+    let summary = s.summary();
+    assert!(summary.contains("\ncontested\n  bur\n"));
+
+    s.parse(&Power::from("Austria"), String::from("gal-war"));
+    s.parse(&Power::from("Germany"), String::from("sil s gal-war"));
+    s.apply();
+    let summary = s.summary();
+    assert!(summary.contains("\ndislodged\n  A war (Russia)\n"));
+}
+
+#[test]
+fn test_load_dislodged_without_attackers() {
+    let mut s = Stpsyr::new("data/standard.csv");
+    s.parse(&Power::from("Germany"), String::from("ber-pru"));
+    s.parse(&Power::from("Austria"), String::from("vie-gal"));
+    s.apply();
+    s.parse(&Power::from("Germany"), String::from("pru-war"));
+    s.parse(&Power::from("Austria"), String::from("gal s pru-war"));
+    s.apply();
+    assert_eq!(s.phase, Phase::FallRetreats);
+    let json = s.dump_json();
+    let saved = Stpsyr::parse_json(&json).unwrap();
+    assert_eq!(saved.dislodged.len(), 1);
+    assert!(!saved.retreat_is_legal(
+        &Power::from("Russia"),
+        &Province::from("war"),
+        &RetreatAction::Move {
+            to: Province::from("pru")
+        }
+    ));
+
+    // games saved before attackers were kept still load, with the retreat
+    //   rules they had then
+    let mut old: serde_json::Value = serde_json::from_str(&json).unwrap();
+    old.as_object_mut().unwrap().remove("attacked_from");
+    let old = Stpsyr::parse_json(&old.to_string()).unwrap();
+    assert_eq!(old.dislodged.len(), 1);
+    assert!(old.retreat_is_legal(
+        &Power::from("Russia"),
+        &Province::from("war"),
+        &RetreatAction::Move {
+            to: Province::from("pru")
+        }
+    ));
+}
+
+#[test]
+fn test_validate_map() {
+    assert_eq!(
+        Stpsyr::validate_map("data/standard.csv"),
+        Vec::<String>::new()
+    );
+
+    let path = test_dir("validate-map").join("bad-map.csv");
+    std::fs::write(
+        &path,
+        "name,sc,owner,unit,fleet borders,army borders,center x, center y
+aaa,true,Red,Army,,bbb ccc,0,0
+bbb,false,,Tank,,aaa,0,0
+ccc,false,,,,,zero,0
+",
+    )
+    .unwrap();
+    let problems = Stpsyr::validate_map(path.to_str().unwrap());
+    assert_eq!(problems.len(), 3, "{:?}", problems);
+    assert!(problems[0].starts_with("line 3: "));
+    assert!(problems[1].starts_with("line 4: "));
+    assert_eq!(problems[2], "line 2: unknown army border ccc");
+}
+
+#[test]
+fn test_cli() {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let stpsyr = env!("CARGO_BIN_EXE_stpsyr");
+    let game = test_dir("cli").join("game.json");
+    let game = game.to_str().unwrap();
+
+    let status = Command::new(stpsyr)
+        .args(["new", "data/standard.csv", game])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let orders = |power: &str, orders: &[u8]| {
+        let mut child = Command::new(stpsyr)
+            .args(["orders", game, power])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(orders).unwrap();
+        child.wait_with_output().unwrap()
+    };
+    let output = orders("italy", b"A ven-tyr\nA rom-ber\n\n");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"1/2 accepted\n");
+    let output = orders("Italy", b"A rom-ber\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(output.stdout, b"0/1 accepted\n");
+    assert_eq!(orders("Narnia", b"A ven-tyr\n").status.code(), Some(1));
+
+    // the engine's diagnostics stay off stdout
+    let adjudicate = Command::new(stpsyr)
+        .args(["adjudicate", game])
+        .env_remove("STPSYR_LOG")
+        .output()
+        .unwrap();
+    assert!(adjudicate.status.success());
+    assert_eq!(adjudicate.stdout, b"Fall Diplomacy 1901\n");
+    assert!(adjudicate.stderr.is_empty());
+
+    let show = Command::new(stpsyr).args(["show", game]).output().unwrap();
+    let show = String::from_utf8(show.stdout).unwrap();
+    assert!(show.starts_with("Fall Diplomacy 1901\n"));
+    assert!(show.contains("A tyr"));
+
+    let status = Command::new(stpsyr).arg("bogus").status().unwrap();
+    assert_eq!(status.code(), Some(2));
+}
+
+#[test]
+fn test_repl() {
+    let mut repl = repl::Repl::new(Stpsyr::new("data/standard.csv"));
+    assert!(repl.execute("ven-tyr").starts_with("no power selected"));
+    assert_eq!(repl.execute("power italy"), "entering orders for Italy");
+    assert_eq!(repl.execute("A ven-tyr"), "accepted: A ven-tyr");
+    assert_eq!(repl.execute("A ven-ber"), "rejected: A ven-ber");
+    assert_eq!(repl.execute("rom s"), "rejected: rom s");
+    assert_eq!(repl.execute("orders"), "Italy\n  ven - tyr");
+    assert_eq!(repl.execute("adjudicate"), "Fall Diplomacy 1901");
+    assert!(repl.game.get_unit(&Province::from("tyr")).is_some());
+
+    // undo takes back the adjudication, then the order
+    assert_eq!(repl.execute("undo"), "undone; Spring Diplomacy 1901");
+    assert!(repl.game.get_unit(&Province::from("ven")).is_some());
+    assert_eq!(repl.execute("orders"), "Italy\n  ven - tyr");
+    repl.execute("undo");
+    assert_eq!(repl.execute("orders"), "no orders yet");
+    assert_eq!(repl.execute("undo"), "nothing to undo");
+
+    let mut output = vec![];
+    repl.run(&b"power France\nshow\nquit\nshow\n"[..], &mut output);
+    let output = String::from_utf8(output).unwrap();
+    assert!(
+        output.starts_with("Italy> entering orders for France\nFrance> Spring Diplomacy 1901\n")
+    );
+    assert_eq!(output.matches("Spring Diplomacy 1901").count(), 1);
+}
+
+#[test]
+fn test_server() {
+    let mut server = server::Server::new("data");
+    let created = server.handle("POST", "/games", None, r#"{"map": "standard"}"#);
+    assert_eq!(created.status, 201);
+    let created: serde_json::Value = serde_json::from_slice(&created.body).unwrap();
+    assert_eq!(created["id"], 1);
+    let master = created["master_token"].as_str().unwrap();
+    let italy = created["tokens"]["Italy"].as_str().unwrap();
+    let france = created["tokens"]["France"].as_str().unwrap();
+    assert_ne!(italy, france);
+
+    assert_eq!(
+        server
+            .handle("POST", "/games", None, r#"{"map": "../data/standard"}"#)
+            .status,
+        400
+    );
+    assert_eq!(server.handle("GET", "/games/2", None, "").status, 404);
+
+    let orders = server.handle(
+        "POST",
+        "/games/1/orders/italy",
+        Some(italy),
+        "A ven-tyr\nA rom-ber\n",
+    );
+    assert_eq!(orders.body, br#"{"accepted":1,"submitted":2}"#);
+    assert_eq!(
+        server
+            .handle("POST", "/games/1/orders/atlantis", Some(master), "")
+            .status,
+        404
+    );
+    // only Italy (or the game master) can order for Italy
+    assert_eq!(
+        server
+            .handle("POST", "/games/1/orders/italy", None, "A rom-apu")
+            .status,
+        401
+    );
+    assert_eq!(
+        server
+            .handle("POST", "/games/1/orders/italy", Some(france), "A rom-apu")
+            .status,
+        403
+    );
+    server.handle("POST", "/games/1/orders/france", Some(france), "A par-pic");
+    server.handle("POST", "/games/1/orders/france", Some(france), "A par-bur");
+    let listed = server.handle("GET", "/games/1/orders/france", Some(france), "");
+    assert_eq!(listed.body, br#"["par - bur"]"#);
+    server.handle("POST", "/games/1/orders/france", Some(france), "A mar-spa");
+    server.handle("DELETE", "/games/1/orders/france", Some(france), "");
+    server.handle("POST", "/games/1/orders/france", Some(france), "A par-bur");
+
+    // each power sees only its own pending orders
+    let pending = |server: &mut server::Server, token| {
+        let state = server.handle("GET", "/games/1", token, "");
+        let state = Stpsyr::parse_json(std::str::from_utf8(&state.body).unwrap()).unwrap();
+        state
+            .orders
+            .iter()
+            .map(|o| o.to_string())
+            .collect::<Vec<String>>()
+    };
+    assert_eq!(pending(&mut server, Some(italy)), vec!["ven - tyr"]);
+    assert_eq!(pending(&mut server, Some(france)), vec!["par - bur"]);
+    assert_eq!(pending(&mut server, None), Vec::<String>::new());
+    assert_eq!(pending(&mut server, Some(master)).len(), 2);
+
+    assert_eq!(
+        server
+            .handle("POST", "/games/1/adjudicate", Some(italy), "")
+            .status,
+        403
+    );
+    let adjudicated = server.handle("POST", "/games/1/adjudicate", Some(master), "");
+    assert_eq!(
+        adjudicated.body,
+        br#"{"phase":"FallDiplomacy","year":1901}"#
+    );
+
+    let state = server.handle("GET", "/games/1", None, "");
+    let state = Stpsyr::parse_json(std::str::from_utf8(&state.body).unwrap()).unwrap();
+    assert!(state.get_unit(&Province::from("tyr")).is_some());
+
+    // once adjudicated, everyone's orders are public
+    let results = server.handle("GET", "/games/1/results", None, "");
+    let results: PhaseRecord = serde_json::from_slice(&results.body).unwrap();
+    assert_eq!(results.orders.len(), 2);
+    assert!(results.orders.iter().all(|o| o.resolution));
+
+    let map = server.handle("GET", "/games/1/map.svg", None, "");
+    assert_eq!(map.content_type, "image/svg+xml");
+    assert!(map.body.starts_with(b"<svg"));
+
+    // missing artwork is an error rather than a crash
+    server.map_dir = String::from("no-such-dir");
+    assert_eq!(
+        server.handle("GET", "/games/1/map.svg", None, "").status,
+        500
+    );
+    server.map_dir = String::from("data");
+
+    // so is a panic while adjudicating, which leaves the game as it was
+    server.handle("POST", "/games/1/orders/italy", Some(italy), "A tyr-mun");
+    let game = server.games.get_mut(&1).unwrap();
+    game.state.map.retain(|r| r.province.name != "mun");
+    assert_eq!(
+        server
+            .handle("POST", "/games/1/adjudicate", Some(master), "")
+            .status,
+        500
+    );
+    assert_eq!(pending(&mut server, Some(italy)), vec!["tyr - mun"]);
+    assert_eq!(server.games[&1].state.phase, Phase::FallDiplomacy);
+}
+
+#[test]
+fn test_later_orders_replace_earlier_ones() {
+    let mut s = Stpsyr::new("data/standard.csv");
+    let france = Power::from("France");
+    s.parse(&france, String::from("A par H\nA mar-spa"));
+    s.parse(&france, String::from("A par-bur\nA mar-gas"));
+    assert_eq!(s.pending_orders(&france), vec!["par - bur", "mar - gas"]);
+    // a rejected order leaves the earlier one in place
+    s.parse(&france, String::from("A par-mun"));
+    assert_eq!(s.pending_orders(&france), vec!["par - bur", "mar - gas"]);
+
+    s.parse(&Power::from("Italy"), String::from("A ven-tyr"));
+    s.clear_orders(&france);
+    assert!(s.pending_orders(&france).is_empty());
+    s.parse(&france, String::from("A par-pic"));
+    s.apply();
+    assert!(s.get_unit(&Province::from("pic")).is_some());
+    assert!(s.get_unit(&Province::from("tyr")).is_some());
+    assert!(s.get_unit(&Province::from("mar")).is_some());
+
+    // builds: a later build in the same province replaces the earlier one
+    //   even though France only has one build
+    s.parse(&france, String::from("A mar-spa"));
+    s.apply();
+    assert_eq!(s.phase, Phase::Builds);
+    s.parse(&france, String::from("build A mar"));
+    s.parse(&france, String::from("build F mar"));
+    assert_eq!(s.pending_orders(&france), vec!["build F mar"]);
+    s.parse(&france, String::from("build A par"));
+    assert_eq!(s.pending_orders(&france), vec!["build F mar"]);
+}
+
+#[test]
+fn test_deadlines() {
+    use stpsyr::deadlines::ManualClock;
+
+    let clock = ManualClock::new(1000);
+    let mut s = Stpsyr::new("data/standard.csv");
+    s.phase_lengths.diplomacy = Some(100);
+    assert!(!s.tick(&clock));
+    assert_eq!(s.deadline, Some(1100));
+
+    // everyone being ready ends the phase early
+    s.parse(&Power::from("Italy"), String::from("A ven-tyr"));
+    for power in s.powers_to_order() {
+        s.set_ready(&power, true);
+    }
+    s.set_ready(&Power::from("Italy"), false);
+    clock.advance(50);
+    assert!(!s.tick(&clock));
+    s.set_ready(&Power::from("Italy"), true);
+    assert!(s.tick(&clock));
+    assert_eq!(s.phase, Phase::FallDiplomacy);
+    assert!(s.get_unit(&Province::from("tyr")).is_some());
+    assert!(s.ready.is_empty());
+    assert_eq!(s.deadline, Some(1150));
+
+    // otherwise the deadline does, and everyone who didn't order NMRs
+    clock.advance(99);
+    assert!(!s.tick(&clock));
+    clock.advance(1);
+    assert!(s.is_due(&clock));
+    assert!(s.tick(&clock));
+    assert_eq!(s.phase, Phase::SpringDiplomacy);
+    assert_eq!(s.year, 1902);
+    assert_eq!(s.history.last().unwrap().nmrs.len(), 7);
+
+    // the server checks deadlines on every request
+    let mut server = server::Server::with_clock("data", Box::new(clock.clone()));
+    let created = server.handle(
+        "POST",
+        "/games",
+        None,
+        r#"{"phase_lengths": {"diplomacy": 60}}"#,
+    );
+    let created: serde_json::Value = serde_json::from_slice(&created.body).unwrap();
+    let italy = created["tokens"]["Italy"].as_str().unwrap();
+    let ready = server.handle("POST", "/games/1/ready/italy", Some(italy), "");
+    let ready: serde_json::Value = serde_json::from_slice(&ready.body).unwrap();
+    assert_eq!(ready["adjudicated"], false);
+    assert_eq!(server.games[&1].state.ready, vec![Power::from("Italy")]);
+
+    clock.advance(60);
+    server.handle("GET", "/games", None, "");
+    assert_eq!(server.games[&1].state.phase, Phase::FallDiplomacy);
+}
+
+#[test]
+fn test_deadlines_in_retreats_and_builds() {
+    use stpsyr::deadlines::ManualClock;
+
+    let clock = ManualClock::new(1000);
+    let mut s = Stpsyr::new("data/standard.csv");
+    s.phase_lengths = PhaseLengths {
+        diplomacy: Some(100),
+        retreats: Some(100),
+        builds: Some(100),
+    };
+    s.tick(&clock);
+    let (austria, italy) = (Power::from("Austria"), Power::from("Italy"));
+    let (russia, turkey) = (Power::from("Russia"), Power::from("Turkey"));
+
+    s.parse(&austria, String::from("F tri-alb"));
+    s.parse(&italy, String::from("A rom-apu"));
+    s.parse(&turkey, String::from("A smy-arm\nF ank-bla"));
+    clock.advance(100);
+    assert!(s.tick(&clock));
+
+    // Italy walks into the empty tri, Turkey dislodges the fleet in sev
+    s.parse(&italy, String::from("A ven-tri"));
+    s.parse(&turkey, String::from("A arm-sev\nF bla S A arm-sev"));
+    clock.advance(100);
+    assert!(s.tick(&clock));
+    assert_eq!(s.phase, Phase::FallRetreats);
+
+    // Russia doesn't retreat
+    clock.advance(100);
+    assert!(s.tick(&clock));
+    assert_eq!(s.phase, Phase::Builds);
+    assert_eq!(s.adjust_deltas()[&austria], -1);
+
+    // Austria doesn't disband, so its unit farthest from home goes
+    clock.advance(100);
+    assert!(s.tick(&clock));
+    assert_eq!(s.phase, Phase::SpringDiplomacy);
+    assert!(s.get_unit(&Province::from("alb")).is_none());
+    assert!(s.get_unit(&Province::from("vie")).is_some());
+    assert!(s.get_unit(&Province::from("bud")).is_some());
+
+    let counts = s.nmr_counts();
+    // the fall, then builds
+    assert_eq!(counts[&austria], 2);
+    // builds only
+    assert_eq!(counts[&italy], 1);
+    assert_eq!(counts[&turkey], 1);
+    // both diplomacy phases, then retreats
+    assert_eq!(counts[&russia], 3);
+    assert_eq!(counts[&Power::from("England")], 2);
+}
+
+#[test]
+fn test_storage() {
+    let path = test_dir("storage").join("games.db");
+
+    let mut s = Stpsyr::new("data/standard.csv");
+    s.parse(&Power::from("Italy"), String::from("A ven-tyr"));
+    s.apply();
+    s.parse(&Power::from("Italy"), String::from("A tyr-mun"));
+
+    {
+        let mut store = storage::Storage::open(&path).unwrap();
+        store.save(7, &s).unwrap();
+        store.set_metadata(7, "hello").unwrap();
+    }
+
+    let mut store = storage::Storage::open(&path).unwrap();
+    assert_eq!(store.ids().unwrap(), vec![7]);
+    assert_eq!(store.metadata(7).unwrap(), Some(String::from("hello")));
+    let loaded = store.load(7).unwrap().unwrap();
+    assert_eq!(loaded.phase, Phase::FallDiplomacy);
+    assert_eq!(
+        loaded.pending_orders(&Power::from("Italy")),
+        vec!["tyr - mun"]
+    );
+    assert_eq!(loaded.history.len(), 1);
+    assert_eq!(loaded.history[0].orders[0].to_string(), "ven - tyr");
+
+    // saving again adds the new phases
+    let mut next = loaded.clone();
+    next.apply();
+    store.save(7, &next).unwrap();
+    let history = store.history(7).unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[1].phase, Phase::FallDiplomacy);
+    assert!(store.load(8).unwrap().is_none());
+
+    // and replaces phases that were rolled back and adjudicated again
+    let mut redone = loaded.clone();
+    redone.clear_orders(&Power::from("Italy"));
+    redone.parse(&Power::from("Italy"), String::from("A tyr-vie"));
+    redone.apply();
+    store.save(7, &redone).unwrap();
+    let history = store.history(7).unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[1].orders[0].to_string(), "tyr - vie");
+
+    // corrupt rows are errors
+    {
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute("UPDATE phases SET record = '{' WHERE seq = 1", [])
+            .unwrap();
+        assert!(store.history(7).is_err());
+        assert!(store.load(7).is_err());
+        conn.execute("UPDATE games SET state = 'null'", []).unwrap();
+        assert!(store.load(7).is_err());
+    }
+
+    store.delete(7).unwrap();
+    assert!(store.ids().unwrap().is_empty());
+    assert!(store.history(7).unwrap().is_empty());
+
+    // a server picks up where it left off after a restart
+    let token = {
+        let mut server = server::Server::new("data");
+        server.attach_storage(store).unwrap();
+        let created = server.handle("POST", "/games", None, "");
+        let created: serde_json::Value = serde_json::from_slice(&created.body).unwrap();
+        let token = created["master_token"].as_str().unwrap().to_string();
+        server.handle("POST", "/games/1/orders/italy", Some(&token), "A ven-tyr");
+        server.handle("POST", "/games/1/adjudicate", Some(&token), "");
+        token
+    };
+    let mut server = server::Server::new("data");
+    server
+        .attach_storage(storage::Storage::open(&path).unwrap())
+        .unwrap();
+    let state = server.handle("GET", "/games/1", Some(&token), "");
+    let state = Stpsyr::parse_json(std::str::from_utf8(&state.body).unwrap()).unwrap();
+    assert_eq!(state.phase, Phase::FallDiplomacy);
+    assert_eq!(state.history.len(), 1);
+    let created = server.handle("POST", "/games", None, "");
+    let created: serde_json::Value = serde_json::from_slice(&created.body).unwrap();
+    assert_eq!(created["id"], 2);
+}
+
+#[test]
+fn test_press() {
+    let (austria, france, italy) = (
+        Power::from("Austria"),
+        Power::from("France"),
+        Power::from("Italy"),
+    );
+    let mut s = Stpsyr::new("data/standard.csv");
+    assert!(s
+        .send_press(&italy, &[Power::from("Austria")], "lepanto?", false)
+        .is_ok());
+    assert!(s
+        .send_press(&france, &[], "peace in our time", false)
+        .is_ok());
+    assert_eq!(
+        s.send_press(&italy, &[Power::from("France")], "boo", true),
+        Err(PressError::NoGrayPress)
+    );
+    assert_eq!(
+        s.send_press(&italy, &[], "  ", false),
+        Err(PressError::EmptyMessage)
+    );
+
+    // gray press hides the sender from everyone else
+    s.press_rules.gray = true;
+    s.apply();
+    assert!(s
+        .send_press(&italy, &[Power::from("France")], "boo", true)
+        .is_ok());
+    assert_eq!(s.press[2].phase, Phase::FallDiplomacy);
+
+    assert_eq!(s.press_for(Some(&austria)).len(), 2);
+    assert_eq!(s.press_for(None).len(), 1);
+    let to_france = s.press_for(Some(&france));
+    assert_eq!(to_france.len(), 2);
+    assert_eq!(to_france[1].from, None);
+    assert_eq!(s.press_for(Some(&italy))[2].from, Some(italy.clone()));
+    assert_eq!(s.redacted(Some(&austria)).press.len(), 2);
+
+    s.press_rules = PressRules::gunboat();
+    assert_eq!(
+        s.send_press(&italy, &[Power::from("Austria")], "hi", false),
+        Err(PressError::NoPrivatePress)
+    );
+    assert_eq!(
+        s.send_press(&italy, &[], "hi", false),
+        Err(PressError::NoBroadcastPress)
+    );
+
+    // and over the server, where the token says who is sending
+    let mut server = server::Server::new("data");
+    let created = server.handle("POST", "/games", None, r#"{"gunboat": true}"#);
+    let created: serde_json::Value = serde_json::from_slice(&created.body).unwrap();
+    let italy_token = created["tokens"]["Italy"].as_str().unwrap();
+    let sent = server.handle(
+        "POST",
+        "/games/1/press/italy",
+        Some(italy_token),
+        r#"{"body": "hi"}"#,
+    );
+    assert_eq!(sent.status, 403);
+
+    let created = server.handle("POST", "/games", None, "");
+    let created: serde_json::Value = serde_json::from_slice(&created.body).unwrap();
+    let italy_token = created["tokens"]["Italy"].as_str().unwrap();
+    let france_token = created["tokens"]["France"].as_str().unwrap();
+    let sent = server.handle(
+        "POST",
+        "/games/2/press/italy",
+        Some(italy_token),
+        r#"{"to": ["Austria"], "body": "lepanto?"}"#,
+    );
+    assert_eq!(sent.status, 201);
+    let sent = server.handle(
+        "POST",
+        "/games/2/press/italy",
+        Some(france_token),
+        r#"{"body": "forged"}"#,
+    );
+    assert_eq!(sent.status, 403);
+    let press = server.handle("GET", "/games/2/press", Some(france_token), "");
+    let press: serde_json::Value = serde_json::from_slice(&press.body).unwrap();
+    assert_eq!(press, serde_json::json!([]));
+    let press = server.handle("GET", "/games/2/press", Some(italy_token), "");
+    let press: serde_json::Value = serde_json::from_slice(&press.body).unwrap();
+    assert_eq!(press[0]["body"], "lepanto?");
+}
+
+#[test]
+fn test_pbem() {
+    use stpsyr::deadlines::ManualClock;
+    use stpsyr::pbem::{Judge, Mail};
+
+    let dir = test_dir("pbem");
+    let (maildir, outbox) = (dir.join("Maildir"), dir.join("outbox"));
+    std::fs::create_dir_all(maildir.join("new")).unwrap();
+
+    let mut s = Stpsyr::new("data/standard.csv");
+    s.phase_lengths.diplomacy = Some(100);
+    let mut judge = Judge::new(s, &maildir, &outbox);
+    judge.add_player("Italy@Example.com", Power::from("Italy"), None);
+    judge.add_player(
+        "austria@example.com",
+        Power::from("Austria"),
+        Some("swordfish".into()),
+    );
+
+    std::fs::write(
+        maildir.join("new/1"),
+        "From: Someone <italy@example.com>\r\nSubject: spring\r\n\r\n\
+         A ven-tyr\r\nA rom-tun\r\nready\r\n-- \r\nA nap-ion\r\n",
+    )
+    .unwrap();
+    std::fs::write(
+        maildir.join("new/2"),
+        "From: austria@example.com\nSubject: orders\n\nA vie-tyr\n",
+    )
+    .unwrap();
+    std::fs::write(
+        maildir.join("new/3"),
+        "From: mallory@example.com\nSubject: orders\n\nA vie-gal\n",
+    )
+    .unwrap();
+    assert_eq!(judge.check_mail().unwrap(), 3);
+    assert!(maildir.join("cur/1:2,S").exists());
+    assert_eq!(judge.check_mail().unwrap(), 0);
+
+    let reply = Mail::parse(&std::fs::read_to_string(outbox.join("000001.eml")).unwrap());
+    assert_eq!(reply.to, "italy@example.com");
+    assert_eq!(reply.subject, "Re: spring");
+    assert!(reply.body.contains("accepted:\n  A ven-tyr\n"));
+    assert!(reply.body.contains("rejected:\n  A rom-tun\n"));
+    assert!(reply.body.contains("you are marked as ready"));
+    assert_eq!(
+        judge.game.pending_orders(&Power::from("Italy")),
+        vec!["ven - tyr"]
+    );
+    let reply = Mail::parse(&std::fs::read_to_string(outbox.join("000002.eml")).unwrap());
+    assert!(reply.body.contains("password"));
+    assert!(judge
+        .game
+        .pending_orders(&Power::from("Austria"))
+        .is_empty());
+    let reply = Mail::parse(&std::fs::read_to_string(outbox.join("000003.eml")).unwrap());
+    assert!(reply.body.contains("aren't playing"));
+
+    // results go to every player once the deadline passes
+    let clock = ManualClock::new(0);
+    assert!(!judge.tick(&clock).unwrap());
+    clock.advance(100);
+    assert!(judge.tick(&clock).unwrap());
+    assert_eq!(judge.game.phase, Phase::FallDiplomacy);
+    let results = Mail::parse(&std::fs::read_to_string(outbox.join("000004.eml")).unwrap());
+    assert_eq!(results.subject, "Spring Diplomacy 1901 results");
+    assert!(results.body.contains("Italy: ven - tyr\n"));
+    assert!(results.body.contains("Austria: no orders received\n"));
+    assert!(outbox.join("000005.eml").exists());
+
+    // the password line can be in any case and is never echoed back
+    std::fs::write(
+        maildir.join("new/4"),
+        "From: austria@example.com\nSubject: fall\n\nPASSWORD swordfish\nA vie-gal\n",
+    )
+    .unwrap();
+    std::fs::write(
+        maildir.join("new/5"),
+        "From: austria@example.com\nSubject: fall\n\nPassword hunter2\nA bud-rum\n",
+    )
+    .unwrap();
+    // MIME messages are read from their text/plain part
+    std::fs::write(
+        maildir.join("new/6"),
+        "From: italy@example.com\nSubject: fall\nMIME-Version: 1.0\n\
+         Content-Type: multipart/alternative; boundary=\"b1\"\n\n\
+         preamble\n--b1\nContent-Type: text/html\n\n<p>A rom-apu</p>\n\
+         --b1\nContent-Type: text/plain; charset=utf-8\n\
+         Content-Transfer-Encoding: quoted-printable\n\n\
+         A rom=2Dap=\nu\nF nap-ion\n--b1--\n",
+    )
+    .unwrap();
+    std::fs::write(
+        maildir.join("new/7"),
+        "From: italy@example.com\nSubject: fall\nContent-Type: text/html\n\n<p>A tyr-boh</p>\n",
+    )
+    .unwrap();
+    assert_eq!(judge.check_mail().unwrap(), 4);
+
+    let reply = std::fs::read_to_string(outbox.join("000006.eml")).unwrap();
+    assert!(reply.contains("accepted:\n  A vie-gal\n"));
+    assert!(!reply.contains("swordfish"));
+    let reply = std::fs::read_to_string(outbox.join("000007.eml")).unwrap();
+    assert!(reply.contains("wrong or missing password"));
+    assert!(!reply.contains("hunter2"));
+    assert_eq!(
+        judge.game.pending_orders(&Power::from("Austria")),
+        vec!["vie - gal"]
+    );
+    let reply = Mail::parse(&std::fs::read_to_string(outbox.join("000008.eml")).unwrap());
+    assert!(reply.body.contains("accepted:\n  A rom-apu\n  F nap-ion\n"));
+    assert!(!reply.body.contains("rejected"));
+    let reply = Mail::parse(&std::fs::read_to_string(outbox.join("000009.eml")).unwrap());
+    assert!(reply.body.contains("text/html"));
+    assert!(reply.body.contains("plain text"));
+    assert_eq!(
+        judge.game.pending_orders(&Power::from("Italy")),
+        vec!["rom - apu", "nap - ion"]
+    );
+}
+
+#[test]
+fn test_map_svg_is_redacted() {
+    let mut server = server::Server::new("data");
+    let created = server.handle("POST", "/games", None, "");
+    let created: serde_json::Value = serde_json::from_slice(&created.body).unwrap();
+    let master = created["master_token"].as_str().unwrap();
+    let italy = created["tokens"]["Italy"].as_str().unwrap();
+    let austria = created["tokens"]["Austria"].as_str().unwrap();
+
+    // Italy takes Trieste, so there's a build to draw
+    for (austrian, italian) in [
+        ("F tri-adr\nA vie-gal", "A ven-tri"),
+        ("A gal-vie", "A tri-vie"),
+    ] {
+        server.handle("POST", "/games/1/orders/austria", Some(austria), austrian);
+        server.handle("POST", "/games/1/orders/italy", Some(italy), italian);
+        server.handle("POST", "/games/1/adjudicate", Some(master), "");
+    }
+    assert_eq!(server.games[&1].state.phase, Phase::Builds);
+
+    let map = |server: &mut server::Server, token| {
+        server.handle("GET", "/games/1/map.svg", token, "").body
+    };
+    let (public, master_map) = (map(&mut server, None), map(&mut server, Some(master)));
+    let submitted = server.handle("POST", "/games/1/orders/italy", Some(italy), "build A ven");
+    assert_eq!(submitted.status, 200);
+    assert!(map(&mut server, None) == public);
+    assert!(map(&mut server, Some(austria)) == public);
+    assert!(map(&mut server, Some(italy)) != public);
+    assert!(map(&mut server, Some(master)) != master_map);
+}
+
+#[test]
+fn test_events() {
+    use std::net::{TcpListener, TcpStream};
+    use std::time::Duration;
+    use stpsyr::events::Event;
+    use tungstenite::protocol::Role;
+
+    let mut server = server::Server::new("data");
+    let created = server.handle("POST", "/games", None, "");
+    let created: serde_json::Value = serde_json::from_slice(&created.body).unwrap();
+    let master = created["master_token"].as_str().unwrap();
+    let italy = created["tokens"]["Italy"].as_str().unwrap();
+    let austria = created["tokens"]["Austria"].as_str().unwrap();
+
+    // a connected pair of sockets, as if the handshake had just been done
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut subscribe = |url: &str, token: Option<&str>| {
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        server.subscribe(url, token, stream).unwrap();
+        tungstenite::WebSocket::from_raw_socket(client, Role::Client, None)
+    };
+    let mut watcher = subscribe("/games/1/events", None);
+    let mut austrian = subscribe("/games/1/events?token=x", Some(austria));
+    let next = |socket: &mut tungstenite::WebSocket<TcpStream>| -> serde_json::Value {
+        serde_json::from_str(socket.read().unwrap().to_text().unwrap()).unwrap()
+    };
+
+    // pings are answered and closes are acknowledged while waiting for events
+    let mut pinger = subscribe("/games/1/events", None);
+    pinger
+        .send(tungstenite::Message::Ping(vec![1, 2].into()))
+        .unwrap();
+    assert_eq!(
+        pinger.read().unwrap(),
+        tungstenite::Message::Pong(vec![1, 2].into())
+    );
+    pinger.close(None).unwrap();
+    assert!(pinger.read().unwrap().is_close());
+
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    assert_eq!(
+        server
+            .subscribe("/games/9/events", None, stream)
+            .unwrap_err()
+            .status,
+        404
+    );
+    let _ = listener.accept().unwrap();
+
+    server.handle(
+        "POST",
+        "/games/1/orders/austria",
+        Some(austria),
+        "F tri-adr\nA vie-gal",
+    );
+    server.handle("POST", "/games/1/orders/italy", Some(italy), "A ven-tri");
+    let event = next(&mut watcher);
+    assert_eq!(event["type"], "order-submitted");
+    assert_eq!(event["power"]["name"], "Austria");
+    assert!(event.get("orders").is_none());
+    assert_eq!(next(&mut watcher)["power"]["name"], "Italy");
+
+    server.handle("POST", "/games/1/adjudicate", Some(master), "");
+    for _ in 0..2 {
+        assert_eq!(next(&mut austrian)["type"], "order-submitted");
+    }
+    let event = next(&mut austrian);
+    assert_eq!(event["type"], "phase-adjudicated");
+    assert_eq!(event["phase"], "SpringDiplomacy");
+    assert_eq!(event["results"]["orders"].as_array().unwrap().len(), 3);
+    assert_eq!(event["state"]["phase"], "FallDiplomacy");
+
+    // Italy holds on to Trieste, so there are builds at the end of the year
+    server.handle(
+        "POST",
+        "/games/1/orders/austria",
+        Some(austria),
+        "A gal-vie",
+    );
+    server.handle("POST", "/games/1/orders/italy", Some(italy), "A tri-vie");
+    server.handle("POST", "/games/1/adjudicate", Some(master), "");
+    let mut events = vec![];
+    loop {
+        let event = next(&mut watcher);
+        let done = event["type"] == "builds-required";
+        events.push(event);
+        if done {
+            break;
+        }
+    }
+    let types: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+    assert_eq!(
+        types,
+        vec![
+            "phase-adjudicated",
+            "order-submitted",
+            "order-submitted",
+            "phase-adjudicated",
+            "builds-required"
+        ]
+    );
+    assert_eq!(events[4]["deltas"]["Italy"], 1);
+    assert_eq!(events[4]["deltas"]["Austria"], -1);
+
+    // a solo is announced when it happens, not again every phase after
+    let before = Stpsyr::new("data/standard.csv");
+    let mut after = before.clone();
+    for region in after.map.iter_mut().filter(|r| r.sc).take(18) {
+        region.owner = Some(Power::from("Italy"));
+    }
+    after.phase = Phase::FallDiplomacy;
+    let game_over = |events: Vec<Event>| {
+        events
+            .into_iter()
+            .filter(|e| matches!(*e, Event::GameOver { .. }))
+            .count()
+    };
+    assert_eq!(game_over(after.events_after(&before)), 1);
+    let mut later = after.clone();
+    later.phase = Phase::Builds;
+    assert_eq!(game_over(later.events_after(&after)), 0);
+}
+
+#[test]
+fn test_serve() {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::Duration;
+    use tungstenite::Message;
+
+    let free = || {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    };
+    let (address, events_address) = (free(), free());
+    let (tokens, created) = std::sync::mpsc::channel();
+    {
+        let (address, events_address) = (address.clone(), events_address.clone());
+        thread::spawn(move || {
+            let mut server = server::Server::new("data");
+            let game = server.handle("POST", "/games", None, "");
+            let game: serde_json::Value = serde_json::from_slice(&game.body).unwrap();
+            tokens.send(game["tokens"].clone()).unwrap();
+            server.serve(&address, &events_address)
+        });
+    }
+    let italy = created.recv().unwrap()["Italy"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let post = |path: &str, token: &str, body: &str| {
+        let mut stream = TcpStream::connect(&address).unwrap();
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {}\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            path,
+            token,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    };
+    // the server may not be listening yet
+    let connect = |url: String| {
+        for _ in 0..500 {
+            match tungstenite::connect(&url) {
+                Err(tungstenite::Error::Url(_)) => thread::sleep(Duration::from_millis(10)),
+                result => return result,
+            }
+        }
+        panic!("the server never started listening");
+    };
+
+    let (mut socket, _) = connect(format!("ws://{}/games/1/events", events_address)).unwrap();
+    match socket.get_ref() {
+        tungstenite::stream::MaybeTlsStream::Plain(stream) => stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap(),
+        _ => unreachable!(),
+    }
+    match connect(format!("ws://{}/games/9/events", events_address)) {
+        Err(tungstenite::Error::Http(response)) => assert_eq!(response.status(), 404),
+        _ => panic!("subscribed to a game that doesn't exist"),
+    }
+    match connect(format!("ws://{}/games/1/events", address)) {
+        Err(tungstenite::Error::Http(response)) => assert_eq!(response.status(), 400),
+        _ => panic!("subscribed on the HTTP address"),
+    }
+
+    // pings are answered while there's nothing to send
+    socket.send(Message::Ping(vec![7].into())).unwrap();
+    assert_eq!(socket.read().unwrap(), Message::Pong(vec![7].into()));
+
+    let response = post("/games/1/orders/italy", &italy, "A ven-tyr");
+    assert!(response.starts_with("HTTP/1.1 200"));
+    let event: serde_json::Value =
+        serde_json::from_str(socket.read().unwrap().to_text().unwrap()).unwrap();
+    assert_eq!(event["type"], "order-submitted");
+    assert_eq!(event["power"]["name"], "Italy");
+
+    socket.close(None).unwrap();
+    assert!(socket.read().unwrap().is_close());
+}
+
+#[test]
+fn test_observer() {
+    use std::sync::{Arc, Mutex};
+    use stpsyr::observer::{EngineEvent, Observer};
+
+    #[derive(Default)]
+    struct Log(Vec<String>);
+    impl Observer for Log {
+        fn notify(&mut self, event: &EngineEvent) {
+            self.0.push(match *event {
+                EngineEvent::OrderResolved { ref order } => {
+                    format!("{}{}", order, if order.resolution { "" } else { " X" })
+                }
+                EngineEvent::UnitDislodged { ref province, .. } => {
+                    format!("dislodged {:?}", province)
+                }
+                EngineEvent::UnitRetreated {
+                    ref from, ref to, ..
+                } => {
+                    format!("retreated {:?} {:?}", from, to)
+                }
+                EngineEvent::UnitDisbanded { ref province, .. } => {
+                    format!("disbanded {:?}", province)
+                }
+                EngineEvent::ScCaptured {
+                    ref province,
+                    ref from,
+                    ref to,
+                } => format!(
+                    "{} took {:?} from {}",
+                    to.name,
+                    province,
+                    from.as_ref().map_or("nobody", |p| &p.name[..])
+                ),
+                EngineEvent::UnitBuilt { ref province, .. } => format!("built {:?}", province),
+                EngineEvent::PhaseChanged { to, .. } => format!("{} {}", to.0, to.1),
+            });
+        }
+    }
+
+    let (austria, italy) = (Power::from("Austria"), Power::from("Italy"));
+    let log = Arc::new(Mutex::new(Log::default()));
+    let mut s = Stpsyr::new("data/standard.csv");
+    s.add_observer(log.clone());
+
+    // a watched game can still be moved to another thread
+    s.parse(&italy, String::from("A ven-tyr\nA rom-ven"));
+    let mut s = std::thread::spawn(move || {
+        s.apply();
+        s
+    })
+    .join()
+    .unwrap();
+    assert!(log.lock().unwrap().0.contains(&String::from("ven - tyr")));
+    assert_eq!(log.lock().unwrap().0.last().unwrap(), "Fall Diplomacy 1901");
+
+    // forks aren't watched
+    s.what_if(&[(italy.clone(), String::from("A tyr-mun"))]);
+    s.fork().apply();
+    assert_eq!(log.lock().unwrap().0.last().unwrap(), "Fall Diplomacy 1901");
+
+    // going back to an earlier position doesn't stop the game being watched
+    let mut repl = repl::Repl::new(s.fork());
+    repl.game.add_observer(log.clone());
+    repl.execute("power italy");
+    repl.execute("A tyr-mun");
+    repl.execute("undo");
+    repl.execute("adjudicate");
+    let now = format!("{} {}", repl.game.phase, repl.game.year);
+    assert_eq!(log.lock().unwrap().0.last().unwrap(), &now);
+
+    log.lock().unwrap().0.clear();
+    s.parse(&italy, String::from("A ven-tri\nA tyr S A ven-tri"));
+    s.apply();
+    s.parse(&austria, String::from("F tri-alb"));
+    s.apply();
+    s.parse(&italy, String::from("build A ven"));
+    s.parse(&austria, String::from("destroy bud"));
+    s.apply();
+    let log = &log.lock().unwrap().0;
+    let expected = [
+        "ven - tri",
+        "tyr S ven - tri",
+        "dislodged tri",
+        "Italy took tri from Austria",
+        "Fall Retreats 1901",
+        "retreated tri alb",
+        "Builds 1901",
+        "built ven",
+        "disbanded bud",
+        "Spring Diplomacy 1902",
+    ];
+    for line in &expected {
+        assert!(
+            log.contains(&line.to_string()),
+            "{} isn't in {:?}",
+            line,
+            log
+        );
+    }
+    assert_eq!(&log[log.len() - expected.len()..], &expected[..]);
+}
+
+#[test]
+fn test_logging() {
+    use std::sync::Mutex;
+    use std::thread::{self, ThreadId};
+
+    // tests run in parallel, so records are kept by the thread they came from
+    struct Capture(Mutex<Vec<(ThreadId, log::Level, String)>>);
+    struct Fields(String);
+    impl<'kvs> log::kv::VisitSource<'kvs> for Fields {
+        fn visit_pair(
+            &mut self,
+            key: log::kv::Key<'kvs>,
+            value: log::kv::Value<'kvs>,
+        ) -> Result<(), log::kv::Error> {
+            self.0.push_str(&format!(" {}={}", key, value));
+            Ok(())
+        }
+    }
+    impl log::Log for Capture {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+        fn log(&self, record: &log::Record) {
+            let mut fields = Fields(format!("{}", record.args()));
+            record.key_values().visit(&mut fields).unwrap();
+            self.0
+                .lock()
+                .unwrap()
+                .push((thread::current().id(), record.level(), fields.0));
+        }
+        fn flush(&self) {}
+    }
+    static CAPTURE: Capture = Capture(Mutex::new(vec![]));
+    log::set_logger(&CAPTURE).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let mut s = Stpsyr::new("data/standard.csv");
+    s.parse(&Power::from("Italy"), String::from("A ven-tyr"));
+    s.apply();
+    s.render().unwrap();
+
+    let records: Vec<(log::Level, String)> = CAPTURE
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|r| r.0 == thread::current().id())
+        .map(|r| (r.1, r.2.clone()))
+        .collect();
+    let has = |level, record: &str| records.contains(&(level, record.to_string()));
+    assert!(has(
+        log::Level::Debug,
+        "ven - tyr succeeded phase=Spring Diplomacy year=1901"
+    ));
+    assert!(has(
+        log::Level::Info,
+        "no orders received phase=Spring Diplomacy year=1901 power=France"
+    ));
+    assert!(has(
+        log::Level::Info,
+        "adjudicated; next is Fall Diplomacy 1901 phase=Spring Diplomacy year=1901"
+    ));
+    assert!(records
+        .iter()
+        .any(|r| r.0 == log::Level::Trace && r.1.starts_with("region ")));
+}